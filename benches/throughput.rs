@@ -0,0 +1,69 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+use codecrafters_interpreter::lexer::Lexer;
+use codecrafters_interpreter::parser::Parser;
+
+/// Approximate size of each generated source, in bytes.
+const SOURCE_SIZE: usize = 1024 * 1024;
+
+/// Repeat `unit` until the source reaches [`SOURCE_SIZE`].
+fn generate(unit: &str) -> String {
+    unit.repeat(SOURCE_SIZE / unit.len() + 1)
+}
+
+/// Sources dominated by different kinds of tokens.
+fn sources() -> Vec<(&'static str, String)> {
+    vec![
+        (
+            "identifiers",
+            generate("foo bar_baz quux123 android classy orchid\n"),
+        ),
+        ("numbers", generate("1 23 456.789 0.5 1234567 42.0\n")),
+        (
+            "operators",
+            generate("+ - * / == != <= >= < > ! = ( ) { } ; , .\n"),
+        ),
+    ]
+}
+
+fn bench_tokenize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tokenize");
+    for (name, source) in sources() {
+        group.throughput(Throughput::Bytes(source.len() as u64));
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut lexer = Lexer::new(black_box(source.clone()));
+                lexer.tokenize().unwrap();
+                lexer.tokens().len()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for (name, source) in [
+        ("numbers", generate("1; 23; 456.789; 0.5; 1234567; 42.0;\n")),
+        ("groups", generate("(!true); (\"abc\"); (nil); -1;\n")),
+        (
+            "binary",
+            generate("1 + 2 * 3 - 4 / 5 == 6 < 7 and !(8 >= 9) or 10 != -11;\n"),
+        ),
+    ] {
+        let mut lexer = Lexer::new(source.clone());
+        lexer.tokenize().unwrap();
+        group.throughput(Throughput::Bytes(source.len() as u64));
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut parser = Parser::new(black_box(lexer.tokens()), lexer.token_lines());
+                parser.parse().unwrap();
+                parser.output().len()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_tokenize, bench_parse);
+criterion_main!(benches);
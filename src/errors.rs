@@ -2,11 +2,254 @@ use thiserror::Error;
 
 pub type AppResult<T> = anyhow::Result<T>;
 
+/// Exit code for invalid command line usage.
+pub const EXIT_USAGE: i32 = 64;
+
+/// Exit code for errors found when tokenizing or parsing.
+pub const EXIT_LEX_PARSE_ERROR: i32 = 65;
+
+/// Exit code for errors happened when evaluating.
+pub const EXIT_RUNTIME_ERROR: i32 = 70;
+
 #[derive(Debug, Clone, Error)]
 pub enum AppError {
-    #[error("[line {line}] Error: Unexpected character: {token}")]
-    UnexpectedChar { line: usize, token: String },
+    #[error("[line {line}, col {col}] Error: Unexpected character: {token}")]
+    UnexpectedChar {
+        line: usize,
+        col: usize,
+        token: String,
+    },
+
+    #[error("[line {line}, col {col}] Error: Unterminated string.")]
+    UnterminatedString { line: usize, col: usize },
+
+    #[error("[line {line}, col {col}] Error: Unterminated block comment.")]
+    UnterminatedComment { line: usize, col: usize },
+
+    #[error("[line {line}, col {col}] Error: Identifier too long.")]
+    IdentifierTooLong { line: usize, col: usize },
+
+    #[error("[line {line}, col {col}] Error: Invalid escape sequence: {seq}")]
+    InvalidEscape {
+        line: usize,
+        col: usize,
+        seq: String,
+    },
+
+    #[error("Operand must be a number.")]
+    OperandNotNumber,
+
+    #[error("Operands must be numbers.")]
+    OperandsNotNumbers,
+
+    #[error("Operands must be two numbers or two strings.")]
+    OperandsNotNumbersOrStrings,
+
+    #[error("Arithmetic result not representable.")]
+    NotRepresentable,
+
+    #[error("Repeat count must be a non-negative integer.")]
+    InvalidRepeatCount,
+
+    #[error("Repeated string too long.")]
+    RepeatTooLong,
+
+    #[error("[line {line}] Error {at}: Expect expression.")]
+    ExpectExpression { line: usize, at: String },
+
+    #[error("[line {line}] Error {at}: Expect variable name.")]
+    ExpectVariableName { line: usize, at: String },
+
+    #[error("[line {line}] Error at '=': Invalid assignment target.")]
+    InvalidAssignmentTarget { line: usize },
+
+    #[error("[line {line}] Error {at}: Expect operator between operands.")]
+    ExpectOperator { line: usize, at: String },
+
+    #[error("Division by zero.")]
+    DivisionByZero,
+
+    #[error("Undefined variable '{name}'.")]
+    UndefinedVariable { name: String },
+
+    #[error("[line {line}] Error {at}: Expect '{expected}' after {after}.")]
+    ExpectedToken {
+        line: usize,
+        at: String,
+        expected: String,
+        after: &'static str,
+    },
+
+    #[error("[line {line}] Error {at}: Already a variable named '{name}' in this scope.")]
+    AlreadyDeclared {
+        line: usize,
+        at: String,
+        name: String,
+    },
+
+    /// Runtime `error` happened when evaluating code at `line`.
+    #[error("{error}\n[line {line}]")]
+    Runtime { line: usize, error: Box<AppError> },
+}
+
+impl AppError {
+    /// Stable code identifying the kind of error, for tools not parsing the message.
+    ///
+    /// Never change or reuse a code, new errors take the next free one.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::UnexpectedChar { .. } => "E0001",
+            AppError::UnterminatedString { .. } => "E0002",
+            AppError::IdentifierTooLong { .. } => "E0003",
+            AppError::InvalidEscape { .. } => "E0004",
+            AppError::OperandNotNumber => "E0005",
+            AppError::OperandsNotNumbers => "E0006",
+            AppError::OperandsNotNumbersOrStrings => "E0007",
+            AppError::NotRepresentable => "E0008",
+            AppError::InvalidRepeatCount => "E0009",
+            AppError::UnterminatedComment { .. } => "E0010",
+            AppError::DivisionByZero => "E0011",
+            AppError::UndefinedVariable { .. } => "E0012",
+            AppError::ExpectedToken { .. } => "E0013",
+            AppError::RepeatTooLong => "E0014",
+            AppError::ExpectExpression { .. } => "E0015",
+            AppError::ExpectVariableName { .. } => "E0016",
+            AppError::InvalidAssignmentTarget { .. } => "E0017",
+            AppError::ExpectOperator { .. } => "E0018",
+            AppError::AlreadyDeclared { .. } => "E0019",
+            AppError::Runtime { error, .. } => error.code(),
+        }
+    }
+
+    /// The exit code of the process when failed with current error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            AppError::UnexpectedChar { .. }
+            | AppError::UnterminatedString { .. }
+            | AppError::UnterminatedComment { .. }
+            | AppError::IdentifierTooLong { .. }
+            | AppError::InvalidEscape { .. }
+            | AppError::ExpectedToken { .. }
+            | AppError::ExpectExpression { .. }
+            | AppError::ExpectVariableName { .. }
+            | AppError::InvalidAssignmentTarget { .. }
+            | AppError::ExpectOperator { .. }
+            | AppError::AlreadyDeclared { .. } => EXIT_LEX_PARSE_ERROR,
+            AppError::OperandNotNumber
+            | AppError::OperandsNotNumbers
+            | AppError::OperandsNotNumbersOrStrings
+            | AppError::NotRepresentable
+            | AppError::InvalidRepeatCount
+            | AppError::RepeatTooLong
+            | AppError::DivisionByZero
+            | AppError::UndefinedVariable { .. } => EXIT_RUNTIME_ERROR,
+            AppError::Runtime { error, .. } => error.exit_code(),
+        }
+    }
+}
+
+/// Attach the `line` where the runtime error `err` happened.
+///
+/// Errors already having a line and errors not caused by an [`AppError`] are returned unchanged.
+pub fn at_line(err: anyhow::Error, line: usize) -> anyhow::Error {
+    match err.downcast::<AppError>() {
+        Ok(e @ AppError::Runtime { .. }) => e.into(),
+        Ok(e) => AppError::Runtime {
+            line,
+            error: Box::new(e),
+        }
+        .into(),
+        Err(e) => e,
+    }
+}
+
+/// Get the exit code of `err`.
+///
+/// Use `fallback` if `err` is not caused by an [`AppError`].
+pub fn exit_code_of(err: &anyhow::Error, fallback: i32) -> i32 {
+    err.downcast_ref::<AppError>()
+        .map(AppError::exit_code)
+        .unwrap_or(fallback)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One error of each variant, in the order of their codes.
+    fn all_variants() -> Vec<AppError> {
+        let at = || "at end".to_string();
+        vec![
+            AppError::UnexpectedChar {
+                line: 1,
+                col: 1,
+                token: "@".into(),
+            },
+            AppError::UnterminatedString { line: 1, col: 1 },
+            AppError::IdentifierTooLong { line: 1, col: 1 },
+            AppError::InvalidEscape {
+                line: 1,
+                col: 1,
+                seq: "\\q".into(),
+            },
+            AppError::OperandNotNumber,
+            AppError::OperandsNotNumbers,
+            AppError::OperandsNotNumbersOrStrings,
+            AppError::NotRepresentable,
+            AppError::InvalidRepeatCount,
+            AppError::UnterminatedComment { line: 1, col: 1 },
+            AppError::DivisionByZero,
+            AppError::UndefinedVariable { name: "a".into() },
+            AppError::ExpectedToken {
+                line: 1,
+                at: at(),
+                expected: ";".into(),
+                after: "expression",
+            },
+            AppError::RepeatTooLong,
+            AppError::ExpectExpression { line: 1, at: at() },
+            AppError::ExpectVariableName { line: 1, at: at() },
+            AppError::InvalidAssignmentTarget { line: 1 },
+            AppError::ExpectOperator { line: 1, at: at() },
+            AppError::AlreadyDeclared {
+                line: 1,
+                at: at(),
+                name: "a".into(),
+            },
+        ]
+    }
+
+    #[test]
+    fn exit_codes() {
+        let codes: Vec<_> = all_variants().iter().map(AppError::exit_code).collect();
+        let (lex_parse, runtime) = (EXIT_LEX_PARSE_ERROR, EXIT_RUNTIME_ERROR);
+        assert_eq!(
+            codes,
+            [
+                lex_parse, lex_parse, lex_parse, lex_parse, runtime, runtime, runtime, runtime,
+                runtime, lex_parse, runtime, runtime, lex_parse, runtime, lex_parse, lex_parse,
+                lex_parse, lex_parse, lex_parse,
+            ]
+        );
+        for error in all_variants() {
+            let code = error.exit_code();
+            let err = at_line(error.into(), 3);
+            assert_eq!(exit_code_of(&err, 0), code);
+        }
+        assert_eq!(exit_code_of(&anyhow::anyhow!("not an AppError"), 1), 1);
+    }
+
+    #[test]
+    fn error_codes() {
+        let codes: Vec<_> = all_variants().iter().map(AppError::code).collect();
+        let expected: Vec<_> = (1..=19).map(|x| format!("E{x:04}")).collect();
+        assert_eq!(codes, expected);
 
-    #[error("[line {line}] Error: Unterminated string.")]
-    UnterminatedString { line: usize },
+        let runtime = AppError::Runtime {
+            line: 2,
+            error: Box::new(AppError::DivisionByZero),
+        };
+        assert_eq!(runtime.code(), "E0011");
+        assert_eq!(runtime.to_string(), "Division by zero.\n[line 2]");
+    }
 }
@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+
+use crate::errors::{AppError, AppResult};
+use crate::parser::expr::Value;
+
+/// Variables visible in a scope.
+///
+/// Each block has its own environment, names not found in it are looked up in the `parent`
+/// chain, up to the global environment which has no parent.
+#[derive(Debug, Default)]
+pub struct Environment {
+    values: HashMap<String, Value>,
+    parent: Option<Box<Environment>>,
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Child environment of `parent`, for a nested scope.
+    pub fn with_parent(parent: Environment) -> Self {
+        Self {
+            values: HashMap::new(),
+            parent: Some(Box::new(parent)),
+        }
+    }
+
+    /// Enter a nested scope, current environment becomes the parent.
+    pub fn push_scope(&mut self) {
+        let parent = std::mem::take(self);
+        *self = Self::with_parent(parent);
+    }
+
+    /// Leave current scope and restore the parent environment.
+    ///
+    /// Do nothing in the global environment.
+    pub fn pop_scope(&mut self) {
+        if let Some(parent) = self.parent.take() {
+            *self = *parent;
+        }
+    }
+
+    /// Leave current scope, return the parent environment.
+    ///
+    /// Variables defined in current scope are dropped. Return `None` if current environment is the
+    /// global one.
+    pub fn into_parent(self) -> Option<Environment> {
+        self.parent.map(|x| *x)
+    }
+
+    /// Define a variable in current scope.
+    ///
+    /// Defining an existing name again replaces it, names in parent scopes are shadowed.
+    pub fn define(&mut self, name: String, value: Value) {
+        self.values.insert(name, value);
+    }
+
+    /// Value of the variable, from the innermost scope defining it.
+    pub fn get(&self, name: &str) -> AppResult<Value> {
+        match (self.values.get(name), &self.parent) {
+            (Some(v), _) => Ok(v.clone()),
+            (None, Some(parent)) => parent.get(name),
+            (None, None) => Err(AppError::UndefinedVariable {
+                name: name.to_string(),
+            }
+            .into()),
+        }
+    }
+
+    /// Update the variable in the innermost scope defining it.
+    ///
+    /// Unlike [`Environment::define`], the variable must already exist.
+    pub fn assign(&mut self, name: &str, value: Value) -> AppResult<()> {
+        match (self.values.get_mut(name), &mut self.parent) {
+            (Some(v), _) => {
+                *v = value;
+                Ok(())
+            }
+            (None, Some(parent)) => parent.assign(name, value),
+            (None, None) => Err(AppError::UndefinedVariable {
+                name: name.to_string(),
+            }
+            .into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::format_value;
+
+    fn number(value: f64) -> Value {
+        Value::new_number_as(value, true)
+    }
+
+    fn get(env: &Environment, name: &str) -> String {
+        format_value(&env.get(name).unwrap())
+    }
+
+    #[test]
+    fn shadowing_in_nested_scopes() {
+        let mut env = Environment::new();
+        env.define("a".into(), number(1.0));
+        env.define("b".into(), number(2.0));
+
+        env.push_scope();
+        env.define("a".into(), Value::Bool(true));
+        env.push_scope();
+        env.define("a".into(), Value::Nil);
+        assert_eq!(get(&env, "a"), "nil");
+        assert_eq!(get(&env, "b"), "2");
+
+        env.pop_scope();
+        assert_eq!(get(&env, "a"), "true");
+        env.pop_scope();
+        assert_eq!(get(&env, "a"), "1");
+    }
+
+    #[test]
+    fn assign_innermost_definition() {
+        let mut env = Environment::new();
+        env.define("a".into(), number(1.0));
+        env.define("b".into(), number(2.0));
+        env.push_scope();
+        env.define("a".into(), number(10.0));
+        env.assign("a", number(11.0)).unwrap();
+        env.assign("b", number(3.0)).unwrap();
+        assert_eq!(
+            env.assign("c", Value::Nil).unwrap_err().to_string(),
+            "Undefined variable 'c'."
+        );
+
+        env.pop_scope();
+        assert_eq!(get(&env, "a"), "1");
+        assert_eq!(get(&env, "b"), "3");
+        assert!(env.get("c").is_err());
+    }
+}
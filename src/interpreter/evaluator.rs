@@ -0,0 +1,531 @@
+use std::io::Write;
+
+use crate::errors::{AppError, AppResult};
+use crate::parser::expr::{BinaryOp, Expr, LogicalOp, Scope, UnaryOp, Value};
+use crate::parser::stmt::Stmt;
+
+use super::Environment;
+
+/// Evaluate expressions into values.
+///
+/// Each kind of node has its own method and all of them default to the standard lox semantics,
+/// implementors only override the ones they want to customize. Nodes are dispatched by
+/// [`Expr::accept`].
+pub trait Evaluator {
+    /// Variables of the scope currently executing in.
+    fn environment(&mut self) -> &mut Environment;
+
+    /// Execute a statement.
+    ///
+    /// Return the value of an expression statement, `None` for other statements.
+    fn execute(&mut self, stmt: &Stmt) -> AppResult<Option<Value>> {
+        match stmt {
+            Stmt::Expression(e) => self.evaluate(e).map(Some),
+            Stmt::Var { name, initializer } => {
+                let value = match initializer {
+                    Some(v) => self.evaluate(v)?,
+                    None => Value::Nil,
+                };
+                self.environment().define(name.clone(), value);
+                Ok(None)
+            }
+            Stmt::Block(stmts) => {
+                self.environment().push_scope();
+                let result = stmts.iter().try_for_each(|x| self.execute(x).map(|_| ()));
+                // Restore outer variables even if failed.
+                self.environment().pop_scope();
+                result.map(|_| None)
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                if self.evaluate(condition)?.to_bool() {
+                    self.execute(then_branch)?;
+                } else if let Some(v) = else_branch {
+                    self.execute(v)?;
+                }
+                Ok(None)
+            }
+            Stmt::While { condition, body } => {
+                while self.evaluate(condition)?.to_bool() {
+                    self.execute(body)?;
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    fn evaluate(&mut self, expr: &Expr) -> AppResult<Value> {
+        expr.accept(self)
+    }
+
+    fn eval_binary(&mut self, op: &BinaryOp, lhs: &Expr, rhs: &Expr) -> AppResult<Value> {
+        let lhs = self.evaluate(lhs)?;
+        let rhs = self.evaluate(rhs)?;
+        op.apply(&lhs, &rhs)
+    }
+
+    /// Evaluate `rhs` only if `lhs` does not decide the result.
+    fn eval_logical(&mut self, op: &LogicalOp, lhs: &Expr, rhs: &Expr) -> AppResult<Value> {
+        let lhs = self.evaluate(lhs)?;
+        if op.short_circuits(&lhs) {
+            return Ok(lhs);
+        }
+        self.evaluate(rhs)
+    }
+
+    fn eval_unary(&mut self, op: &UnaryOp, operand: &Expr) -> AppResult<Value> {
+        let operand = self.evaluate(operand)?;
+        op.apply(&operand)
+    }
+
+    fn eval_value(&mut self, value: &Value) -> AppResult<Value> {
+        Ok(value.clone())
+    }
+
+    /// Evaluate the expression in scope, an empty scope is `nil`.
+    fn eval_scope(&mut self, scope: &Scope) -> AppResult<Value> {
+        match scope.expr() {
+            Some(v) => self.evaluate(v),
+            None => Ok(Value::Nil),
+        }
+    }
+
+    fn eval_variable(&mut self, name: &str) -> AppResult<Value> {
+        self.environment().get(name)
+    }
+
+    /// Assign to an existing variable, the value is also the result.
+    fn eval_assign(&mut self, name: &str, value: &Expr) -> AppResult<Value> {
+        let value = self.evaluate(value)?;
+        self.environment().assign(name, value.clone())?;
+        Ok(value)
+    }
+}
+
+/// The default evaluator walking the syntax tree with standard lox semantics.
+#[derive(Debug, Default)]
+pub struct TreeWalkEvaluator {
+    /// Divide two integers into an integer, truncating towards zero.
+    ///
+    /// Lox always uses float division, `5 / 2` is `2.5`. With this option it is `2`, while
+    /// `5.0 / 2` is still `2.5`.
+    pub integer_division: bool,
+
+    environment: Environment,
+}
+
+impl Evaluator for TreeWalkEvaluator {
+    fn environment(&mut self) -> &mut Environment {
+        &mut self.environment
+    }
+
+    fn eval_binary(&mut self, op: &BinaryOp, lhs: &Expr, rhs: &Expr) -> AppResult<Value> {
+        let lhs = self.evaluate(lhs)?;
+        let rhs = self.evaluate(rhs)?;
+        match (op, lhs.to_number(), rhs.to_number()) {
+            (BinaryOp::Divide, Some(l), Some(r))
+                if self.integer_division && lhs.is_integer() && rhs.is_integer() && r != 0.0 =>
+            {
+                Ok(Value::new_number_as((l / r).trunc(), true))
+            }
+            _ => op.apply(&lhs, &rhs),
+        }
+    }
+}
+
+/// Evaluator rejecting arithmetic results that can not be represented exactly.
+///
+/// Results overflowing to infinity or beyond 2^53, where integers lose precision, are errors
+/// instead of silently producing `inf` or imprecise numbers.
+#[derive(Debug, Default)]
+pub struct CheckedMathEvaluator {
+    environment: Environment,
+}
+
+impl CheckedMathEvaluator {
+    /// Largest integer every smaller integer can be exactly represented in `f64`.
+    const MAX_EXACT: f64 = 9_007_199_254_740_992.0;
+}
+
+impl Evaluator for CheckedMathEvaluator {
+    fn environment(&mut self) -> &mut Environment {
+        &mut self.environment
+    }
+
+    fn eval_binary(&mut self, op: &BinaryOp, lhs: &Expr, rhs: &Expr) -> AppResult<Value> {
+        let lhs = self.evaluate(lhs)?;
+        let rhs = self.evaluate(rhs)?;
+        let value = op.apply(&lhs, &rhs)?;
+        match value.to_number() {
+            Some(v) if !v.is_finite() || v.abs() > Self::MAX_EXACT => {
+                Err(AppError::NotRepresentable.into())
+            }
+            _ => Ok(value),
+        }
+    }
+}
+
+/// Evaluator logging each evaluated expression and its result to `out`.
+///
+/// Lines are indented by recursion depth, a result is printed after all of its sub-results.
+pub struct TracingEvaluator<W: Write> {
+    out: W,
+    depth: usize,
+    environment: Environment,
+}
+
+impl<W: Write> TracingEvaluator<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            depth: 0,
+            environment: Environment::new(),
+        }
+    }
+}
+
+impl<W: Write> Evaluator for TracingEvaluator<W> {
+    fn environment(&mut self) -> &mut Environment {
+        &mut self.environment
+    }
+
+    fn evaluate(&mut self, expr: &Expr) -> AppResult<Value> {
+        let indent = "  ".repeat(self.depth);
+        writeln!(self.out, "{indent}{}", expr.literal())?;
+        self.depth += 1;
+        let value = expr.accept(self);
+        self.depth -= 1;
+        let value = value?;
+        writeln!(self.out, "{indent}=> {}", value.literal())?;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::exit_code_of;
+    use crate::interpreter::format_value;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    /// Run `input` and collect the printed values of expression statements.
+    fn run_with<E: Evaluator>(evaluator: &mut E, input: &str) -> AppResult<Vec<String>> {
+        let mut lexer = Lexer::new(input.to_string());
+        lexer.tokenize()?;
+        let mut parser = Parser::new(lexer.tokens(), lexer.token_lines());
+        parser.parse()?;
+        let mut values = vec![];
+        for stmt in parser.output() {
+            if let Some(v) = evaluator.execute(stmt)? {
+                values.push(format_value(&v));
+            }
+        }
+        Ok(values)
+    }
+
+    fn run(input: &str) -> AppResult<Vec<String>> {
+        run_with(&mut TreeWalkEvaluator::default(), input)
+    }
+
+    fn run_error(input: &str) -> String {
+        run(input).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn trace_in_evaluation_order() {
+        let mut trace = vec![];
+        let values = run_with(&mut TracingEvaluator::new(&mut trace), "1 + 2 * 3;").unwrap();
+        assert_eq!(values, ["7"]);
+        let trace = String::from_utf8(trace).unwrap();
+        let expected = [
+            "(+ 1.0 (* 2.0 3.0))",
+            "  1.0",
+            "  => 1.0",
+            "  (* 2.0 3.0)",
+            "    2.0",
+            "    => 2.0",
+            "    3.0",
+            "    => 3.0",
+            "  => 6.0",
+            "=> 7.0",
+        ];
+        assert_eq!(trace.lines().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn var_declaration() {
+        assert_eq!(run("var a = 1 + 2; var b; a; b;").unwrap(), ["3", "nil"]);
+    }
+
+    #[test]
+    fn var_redeclaration() {
+        assert_eq!(run("var a = 1; var a = \"x\"; a;").unwrap(), ["x"]);
+        assert_eq!(run("var a = 1; var a = a + 1; a;").unwrap(), ["2"]);
+    }
+
+    #[test]
+    fn var_use_before_declaration() {
+        assert_eq!(
+            run_error("a; var a = 1;"),
+            "Undefined variable 'a'.\n[line 1]"
+        );
+        assert_eq!(run_error("var a = a;"), "Undefined variable 'a'.\n[line 1]");
+    }
+
+    #[test]
+    fn nil_operands() {
+        assert_eq!(
+            run_error("nil + 1;"),
+            "Operands must be two numbers or two strings.\n[line 1]"
+        );
+        for op in ["-", "*", "/", "<", "<=", ">", ">="] {
+            assert_eq!(
+                run_error(&format!("nil {op} 1;")),
+                "Operands must be numbers.\n[line 1]"
+            );
+        }
+        assert_eq!(run_error("-nil;"), "Operand must be a number.\n[line 1]");
+        assert_eq!(
+            run("nil == nil; nil != nil; nil == false; nil == 0; !nil; !!nil;").unwrap(),
+            ["true", "false", "false", "false", "true", "false"]
+        );
+        assert_eq!(
+            run("nil or 1; nil and 1; 1 and nil;").unwrap(),
+            ["1", "nil", "nil"]
+        );
+    }
+
+    #[test]
+    fn boolean_results() {
+        assert_eq!(
+            run("1 < 2; 2 <= 1; 3 > 2; 2 >= 3;").unwrap(),
+            ["true", "false", "true", "false"]
+        );
+        assert_eq!(
+            run("1 == 1; \"a\" != \"a\"; true == true; 1 == \"1\";").unwrap(),
+            ["true", "false", "true", "false"]
+        );
+        assert_eq!(
+            run("!false; !true; !0; !\"\";").unwrap(),
+            ["true", "false", "false", "false"]
+        );
+    }
+
+    #[test]
+    fn plus_operands() {
+        assert_eq!(
+            run("\"foo\" + \"bar\"; 1 + 2; 0.5 + 0.25;").unwrap(),
+            ["foobar", "3", "0.75"]
+        );
+        for input in ["\"foo\" + 1;", "1 + \"foo\";", "true + 1;"] {
+            let err = run(input).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "Operands must be two numbers or two strings.\n[line 1]"
+            );
+            assert_eq!(exit_code_of(&err, 0), 70);
+        }
+    }
+
+    #[test]
+    fn block_shadowing() {
+        // Values are only printed at the top level, collect them in `seen` instead.
+        let input = "var a = \"global\"; var seen = \"\";
+            { var a = \"outer\"; { var a = \"inner\"; seen = seen + a; } seen = seen + a; }
+            seen + a;";
+        assert_eq!(run(input).unwrap(), ["innerouterglobal"]);
+        assert_eq!(run("var a = 1; { a = 2; var b = 3; } a;").unwrap(), ["2"]);
+        assert_eq!(
+            run_error("{ var b = 1; } b;"),
+            "Undefined variable 'b'.\n[line 1]"
+        );
+    }
+
+    #[test]
+    fn runtime_error_line() {
+        assert_eq!(
+            run_error("1;\n\"a\" +\n  -true;"),
+            "Operand must be a number.\n[line 3]"
+        );
+        assert_eq!(
+            run_error("var a = 1;\nwhile (a < 3)\n  a = a + nil;"),
+            "Operands must be two numbers or two strings.\n[line 3]"
+        );
+        let err = run("\n\nb = 1;").unwrap_err();
+        assert_eq!(err.to_string(), "Undefined variable 'b'.\n[line 3]");
+        assert_eq!(
+            err.downcast_ref::<AppError>().map(AppError::code),
+            Some("E0012")
+        );
+        assert_eq!(exit_code_of(&err, 0), 70);
+    }
+
+    #[test]
+    fn if_else() {
+        assert_eq!(run("var a = 0; if (true) a = 1; a;").unwrap(), ["1"]);
+        assert_eq!(run("var a = 0; if (nil) a = 1; a;").unwrap(), ["0"]);
+        assert_eq!(
+            run("var a = 0; if (false) a = 1; else a = 2; a;").unwrap(),
+            ["2"]
+        );
+        let chained = "var a = 2; var b;
+            if (a == 1) b = \"one\"; else if (a == 2) b = \"two\"; else b = \"many\";
+            b;";
+        assert_eq!(run(chained).unwrap(), ["two"]);
+        // The else binds to the nearest if.
+        let dangling = "var a = 0; if (true) if (false) a = 1; else a = 2; a;";
+        assert_eq!(run(dangling).unwrap(), ["2"]);
+        let dangling = "var a = 0; if (false) if (true) a = 1; else a = 2; a;";
+        assert_eq!(run(dangling).unwrap(), ["0"]);
+    }
+
+    #[test]
+    fn for_loop() {
+        let input = "var sum = 0; for (var i = 1; i <= 4; i = i + 1) sum = sum + i; sum;";
+        assert_eq!(run(input).unwrap(), ["10"]);
+        // The loop variable is scoped to the loop.
+        assert_eq!(
+            run_error("for (var i = 0; i < 1; i = i + 1) {} i;"),
+            "Undefined variable 'i'.\n[line 1]"
+        );
+        // Without a condition the loop only ends by failing.
+        let input = "var i = 0; for (;;) { i = i + 1; if (i == 3) i = nil + 1; }";
+        assert_eq!(
+            run_error(input),
+            "Operands must be two numbers or two strings.\n[line 1]"
+        );
+    }
+
+    #[test]
+    fn logical_operators() {
+        assert_eq!(
+            run("nil or 2; false and 1; 1 and 2;").unwrap(),
+            ["2", "false", "2"]
+        );
+        // The right operand is not evaluated when the left one decides.
+        assert_eq!(
+            run("var x = 0; false and (x = 1); true or (x = 2); x;").unwrap(),
+            ["false", "true", "0"]
+        );
+        assert_eq!(run("var x = 0; true and (x = 1); x;").unwrap(), ["1", "1"]);
+    }
+
+    #[test]
+    fn assignment() {
+        assert_eq!(run("var x = 1; x = 3; x;").unwrap(), ["3", "3"]);
+        assert_eq!(
+            run("var a; var b; a = b = 5; a; b;").unwrap(),
+            ["5", "5", "5"]
+        );
+        let err = run("1 = 2;").unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<AppError>().map(|x| x.to_string()),
+            Some("[line 1] Error at '=': Invalid assignment target.".to_string())
+        );
+    }
+
+    #[test]
+    fn nested_unary() {
+        assert_eq!(run("!!true; -(-5); --5;").unwrap(), ["true", "5", "5"]);
+        assert_eq!(
+            run_error("-\"abc\";"),
+            "Operand must be a number.\n[line 1]"
+        );
+    }
+
+    #[test]
+    fn division() {
+        assert_eq!(run("6 / 3; 7 / 2;").unwrap(), ["2", "3.5"]);
+        let err = run("1 / 0;").unwrap_err();
+        assert_eq!(err.to_string(), "Division by zero.\n[line 1]");
+        assert_eq!(
+            err.downcast_ref::<AppError>().map(AppError::code),
+            Some("E0011")
+        );
+    }
+
+    #[test]
+    fn integer_division() {
+        let mut evaluator = TreeWalkEvaluator::default();
+        assert_eq!(
+            run_with(&mut evaluator, "5 / 2; 5.0 / 2;").unwrap(),
+            ["2.5", "2.5"]
+        );
+        let mut evaluator = TreeWalkEvaluator {
+            integer_division: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            run_with(&mut evaluator, "5 / 2; 5.0 / 2;").unwrap(),
+            ["2", "2.5"]
+        );
+    }
+
+    /// Evaluator counting binary operations, with the default semantics otherwise.
+    #[derive(Default)]
+    struct CountingEvaluator {
+        binary_count: usize,
+        environment: Environment,
+    }
+
+    impl Evaluator for CountingEvaluator {
+        fn environment(&mut self) -> &mut Environment {
+            &mut self.environment
+        }
+
+        fn eval_binary(&mut self, op: &BinaryOp, lhs: &Expr, rhs: &Expr) -> AppResult<Value> {
+            self.binary_count += 1;
+            let lhs = self.evaluate(lhs)?;
+            let rhs = self.evaluate(rhs)?;
+            op.apply(&lhs, &rhs)
+        }
+    }
+
+    #[test]
+    fn custom_evaluator() {
+        let mut evaluator = CountingEvaluator::default();
+        let values = run_with(&mut evaluator, "(1 + 2) * 3 - -4 / 2;").unwrap();
+        assert_eq!(values, ["11"]);
+        assert_eq!(evaluator.binary_count, 4);
+    }
+
+    #[test]
+    fn checked_math() {
+        let input = "1e300 * 1e300;";
+        assert_eq!(run(input).unwrap(), ["inf"]);
+        let err = run_with(&mut CheckedMathEvaluator::default(), input).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Arithmetic result not representable.\n[line 1]"
+        );
+        assert_eq!(exit_code_of(&err, 0), 70);
+
+        // Beyond 2^53 integers are imprecise.
+        let input = "9007199254740992 * 2;";
+        assert_eq!(run(input).unwrap(), ["18014398509481984"]);
+        assert!(run_with(&mut CheckedMathEvaluator::default(), input).is_err());
+        assert_eq!(
+            run_with(&mut CheckedMathEvaluator::default(), "1000000 * 1000000;").unwrap(),
+            ["1000000000000"]
+        );
+    }
+
+    #[test]
+    fn scope_restored_after_error() {
+        let mut evaluator = TreeWalkEvaluator::default();
+        let input = "var a = \"global\"; { var a = \"outer\"; { var a = \"inner\"; a + nil; } }";
+        assert!(run_with(&mut evaluator, input).is_err());
+        assert_eq!(run_with(&mut evaluator, "a;").unwrap(), ["global"]);
+        assert_eq!(
+            run_with(&mut evaluator, "{ var b = 1; { b + nil; } }")
+                .unwrap_err()
+                .to_string(),
+            "Operands must be two numbers or two strings.\n[line 1]"
+        );
+        assert!(run_with(&mut evaluator, "b;").is_err());
+    }
+}
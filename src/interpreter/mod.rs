@@ -0,0 +1,134 @@
+mod environment;
+mod evaluator;
+pub use environment::*;
+pub use evaluator::*;
+
+use std::io::Write;
+
+use crate::errors::AppResult;
+use crate::parser::expr::Value;
+use crate::parser::stmt::Stmt;
+
+/// Execute `stmts` in order and write values of expression statements to `out`.
+///
+/// Stop at the first runtime error. `out` is flushed before returning the error so that values
+/// printed before show up ahead of the error message.
+pub fn execute_program<E: Evaluator + ?Sized>(
+    evaluator: &mut E,
+    stmts: &[Stmt],
+    out: &mut dyn Write,
+) -> AppResult<()> {
+    for stmt in stmts {
+        match evaluator.execute(stmt) {
+            Ok(Some(v)) => writeln!(out, "{}", format_value(&v))?,
+            Ok(None) => { /* Nothing to print */ }
+            Err(e) => {
+                out.flush()?;
+                return Err(e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Format a value in the way the evaluate stage prints it.
+///
+/// Strings are printed without quotes, numbers follow [`format_for_evaluate`].
+pub fn format_value(value: &Value) -> String {
+    match value {
+        Value::Number { value, .. } => format_for_evaluate(*value),
+        Value::String { value, .. } => value.clone(),
+        Value::Bool(v) => v.to_string(),
+        Value::Nil => "nil".into(),
+    }
+}
+
+/// Format a number in the way the evaluate stage expects.
+///
+/// Integral numbers have no decimal part and trailing zeros are trimmed: `10.40` is `10.4`,
+/// `10.0` is `10` and `-0.0` is `-0`.
+pub fn format_for_evaluate(n: f64) -> String {
+    // The shortest representation from `Display` already follows these rules.
+    n.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufWriter;
+
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::session::{Session, SharedBuffer};
+
+    fn parse(input: &str) -> Vec<Stmt> {
+        let mut lexer = Lexer::new(input.to_string());
+        lexer.tokenize().unwrap();
+        let mut parser = Parser::new(lexer.tokens(), lexer.token_lines());
+        parser.parse().unwrap();
+        parser.output().clone()
+    }
+
+    /// Session with buffered `out` like the command line, and both writers to check.
+    fn buffered_session() -> (Session, SharedBuffer, SharedBuffer) {
+        let out = SharedBuffer::default();
+        let err = SharedBuffer::default();
+        let session = Session::new(Box::new(BufWriter::new(out.clone())), Box::new(err.clone()));
+        (session, out, err)
+    }
+
+    #[test]
+    fn evaluate_number_format() {
+        assert_eq!(format_for_evaluate(10.40), "10.4");
+        assert_eq!(format_for_evaluate(10.0), "10");
+        assert_eq!(format_for_evaluate(-0.0), "-0");
+        assert_eq!(format_for_evaluate(0.1 + 0.2), "0.30000000000000004");
+        assert_eq!(format_for_evaluate(1e21), "1000000000000000000000");
+    }
+
+    #[test]
+    fn session_captures_output_and_errors() {
+        let (mut session, out, err) = buffered_session();
+
+        let mut lexer = Lexer::new("\"a\" + \"b\"; @\n1 - nil;".to_string());
+        lexer.tokenize().unwrap();
+        lexer.print_errors(&mut session).unwrap();
+        let mut parser = Parser::new(lexer.tokens(), lexer.token_lines());
+        parser.parse().unwrap();
+        let result = execute_program(
+            &mut TreeWalkEvaluator::default(),
+            parser.output(),
+            &mut session.out,
+        );
+        session.report_any(&result.unwrap_err()).unwrap();
+        session.flush().unwrap();
+
+        assert_eq!(out.contents(), "ab\n");
+        assert_eq!(
+            err.contents(),
+            "[line 1, col 12] Error: Unexpected character: @\nOperands must be numbers.\n[line 2]\n"
+        );
+    }
+
+    #[test]
+    fn final_flush_writes_all_values() {
+        let input = (0..100_000).map(|x| format!("{x};\n")).collect::<String>();
+        let stmts = parse(&input);
+        let (mut session, out, _) = buffered_session();
+        execute_program(&mut TreeWalkEvaluator::default(), &stmts, &mut session.out).unwrap();
+        session.flush().unwrap();
+        let expected = (0..100_000).map(|x| format!("{x}\n")).collect::<String>();
+        assert_eq!(out.contents(), expected);
+    }
+
+    #[test]
+    fn values_flushed_before_runtime_error() {
+        let stmts = parse("1;\n2;\n-\"a\";\n3;");
+        let (mut session, out, err) = buffered_session();
+        let e = execute_program(&mut TreeWalkEvaluator::default(), &stmts, &mut session.out)
+            .unwrap_err();
+        assert_eq!(out.contents(), "1\n2\n");
+        session.report_any(&e).unwrap();
+        assert_eq!(err.contents(), "Operand must be a number.\n[line 3]\n");
+    }
+}
@@ -1,8 +1,58 @@
+use std::fmt;
+use std::io::Write;
+
 use crate::errors::{AppError, AppResult};
+use crate::session::Session;
 
 mod tokens;
 pub use tokens::*;
 
+/// Options tweaking the behavior of [`Lexer`].
+#[derive(Debug, Clone, Default)]
+pub struct LexerConfig {
+    /// Max characters count of an identifier.
+    ///
+    /// `None` means no limit.
+    pub max_identifier_length: Option<usize>,
+
+    /// Convert CRLF and CR inside string literals to LF in the string value.
+    ///
+    /// The lexeme always keeps the original characters.
+    pub normalize_newlines_in_strings: bool,
+
+    /// Keep whitespace, line breaks and comments in produced tokens.
+    pub keep_whitespace: bool,
+
+    /// Words recognized as keywords.
+    pub keywords: KeywordSet,
+
+    /// Stop tokenizing at the first error.
+    pub fail_fast: bool,
+}
+
+/// Summary counts of a tokenized input, displayed as `lines: N, tokens: M, errors: K`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LexerStats {
+    /// Count of scanned lines.
+    pub lines: usize,
+
+    /// Count of produced tokens, including the EOF.
+    pub tokens: usize,
+
+    /// Count of errors found.
+    pub errors: usize,
+}
+
+impl fmt::Display for LexerStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "lines: {}, tokens: {}, errors: {}",
+            self.lines, self.tokens, self.errors
+        )
+    }
+}
+
 pub struct Lexer {
     /// The input token.
     input: Vec<char>,
@@ -14,15 +64,31 @@ pub struct Lexer {
     pos: usize,
 
     /// Current line index.
-    ///
-    /// I want to remember the column position at the same time, but leave it now.
     line_idx: usize,
 
+    /// Current column index in line, counted in characters from 1.
+    col_idx: usize,
+
     /// Produced tokens.
     tokens: Vec<Token>,
 
-    /// Has tokenize error or not.
-    has_error: bool,
+    /// Line where each produced token starts, in the same order as tokens.
+    token_lines: Vec<usize>,
+
+    /// Line where the last scanned token starts.
+    token_line: usize,
+
+    /// Errors found when tokenizing.
+    errors: Vec<AppError>,
+
+    /// Options used when consuming tokens.
+    config: LexerConfig,
+
+    /// Scanning stopped at the end of input or an error, no more tokens except the EOF.
+    stopped: bool,
+
+    /// The EOF token is produced, iteration ended.
+    finished: bool,
 }
 
 impl Lexer {
@@ -35,60 +101,311 @@ impl Lexer {
             length,
             pos: 0,
             line_idx: 1,
+            col_idx: 1,
             tokens: vec![],
-            has_error: false,
+            token_lines: vec![],
+            token_line: 1,
+            errors: vec![],
+            config: LexerConfig::default(),
+            stopped: false,
+            finished: false,
         }
     }
 
-    pub fn tokenize(&mut self) -> AppResult<()> {
+    pub fn with_config(mut self, config: LexerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Restore the scan state so that the lexer can tokenize from the beginning again.
+    pub fn reset(&mut self) {
+        self.pos = 0;
+        self.line_idx = 1;
+        self.col_idx = 1;
         self.tokens.clear();
+        self.token_lines.clear();
+        self.token_line = 1;
+        self.errors.clear();
+        self.stopped = false;
+        self.finished = false;
+    }
+
+    /// Replace the input with `new_input` and tokenize it, reusing the allocated buffers.
+    pub fn retokenize(&mut self, new_input: String) -> AppResult<()> {
+        self.input.clear();
+        self.input.extend(new_input.chars());
+        self.length = self.input.len();
+        self.tokenize()
+    }
+
+    /// Tokenize the whole input into [`Lexer::tokens`].
+    pub fn tokenize(&mut self) -> AppResult<()> {
+        let mut tokens = vec![];
+        let result = self.tokenize_with(|t| tokens.push(t));
+        self.tokens = tokens;
+        result
+    }
+
+    /// Tokenize and pass each produced token to `f` instead of collecting them.
+    ///
+    /// Drain the lexer as an iterator from the beginning, see [`Lexer::next`]. `f` receives the
+    /// same tokens as [`Lexer::tokens`] after [`Lexer::tokenize`], ending with [`Token::Eof`].
+    /// Tokens are not saved in [`Lexer::tokens`], errors and [`Lexer::token_lines`] are recorded as
+    /// in [`Lexer::tokenize`].
+    pub fn tokenize_with<F: FnMut(Token)>(&mut self, mut f: F) -> AppResult<()> {
+        self.reset();
+        let mut result = Ok(());
+        for item in self.by_ref() {
+            match item {
+                Ok(t) => f(t),
+                Err(e) => result = Err(e),
+            }
+        }
+        result
+    }
+
+    /// Print the source with ANSI colors by token kind.
+    ///
+    /// Keywords, strings and numbers have their own colors and comments are dimmed, other text is
+    /// printed as it is. Print the plain source if `color` is false.
+    pub fn print_highlighted(&mut self, session: &mut Session, color: bool) -> AppResult<()> {
+        if !color {
+            let result = self.tokenize();
+            write!(session.out, "{}", self.input.iter().collect::<String>())?;
+            return result;
+        }
+
+        let mut spans = vec![];
+        let keep_whitespace = std::mem::replace(&mut self.config.keep_whitespace, true);
+        let result = self.scan(|pos, t| spans.push((pos, t)));
+        self.config.keep_whitespace = keep_whitespace;
+
+        let mut last = 0;
+        for (pos, token) in spans.iter() {
+            let text = self.input[*pos..*pos + token.length()]
+                .iter()
+                .collect::<String>();
+            // Characters not in any token, like unexpected ones.
+            write!(
+                session.out,
+                "{}",
+                self.input[last..*pos].iter().collect::<String>()
+            )?;
+            match Self::color_of(token) {
+                Some(v) => write!(session.out, "{v}{text}\x1b[0m")?,
+                None => write!(session.out, "{text}")?,
+            }
+            last = pos + token.length();
+        }
+        write!(
+            session.out,
+            "{}",
+            self.input[last..].iter().collect::<String>()
+        )?;
+        result
+    }
+
+    /// Scan tokens and pass each one with its start position in input to `f`.
+    fn scan<F: FnMut(usize, Token)>(&mut self, mut f: F) -> AppResult<()> {
+        self.reset();
+        while let Some((pos, t)) = self.step()? {
+            f(pos, t);
+        }
+        Ok(())
+    }
+
+    /// Scan the next token to produce and its start position in input.
+    ///
+    /// Return `None` at the end of input, or at the first unexpected character if
+    /// [`LexerConfig::fail_fast`] is set. Once returned `None` or an error, always return `None`.
+    fn step(&mut self) -> AppResult<Option<(usize, Token)>> {
+        if self.stopped {
+            return Ok(None);
+        }
+        let result = self.scan_token();
+        if !matches!(result, Ok(Some(..))) {
+            self.stopped = true;
+        }
+        result
+    }
+
+    fn scan_token(&mut self) -> AppResult<Option<(usize, Token)>> {
+        // Skip the byte order mark, only allowed at the very beginning.
+        // Not counted as a column.
+        if self.pos == 0 && self.peek() == Some('\u{FEFF}') {
+            self.pos += 1;
+        }
 
         while let Some(ch) = self.peek() {
-            match Token::try_consume(&self.input[self.pos..], self.line_idx)? {
-                Some(t) => {
-                    if let Token::Ignored(IgnoredToken::LineBreak) = t {
-                        self.line_idx += 1;
+            match Token::try_consume(
+                &self.input[self.pos..],
+                self.line_idx,
+                self.col_idx,
+                &self.config,
+            )? {
+                Some(Consumed::Token(t)) => {
+                    let pos = self.pos;
+                    self.token_line = self.line_idx;
+                    self.skip(t.length());
+                    if !t.ignored() || self.config.keep_whitespace {
+                        return Ok(Some((pos, t)));
                     }
-                    self.advance(t.length());
-                    if !t.ignored() {
-                        self.tokens.push(t);
+                }
+                Some(Consumed::Rejected { error, length }) => {
+                    self.errors.push(error);
+                    self.skip(length);
+                    if self.config.fail_fast {
+                        break;
                     }
-                    continue;
                 }
                 None => {
                     // Unknown token.
-                    self.has_error = true;
-                    eprintln!(
-                        "{}",
-                        AppError::UnexpectedChar {
-                            line: self.line_idx,
-                            token: ch.to_string(),
-                        }
-                    );
+                    self.errors.push(AppError::UnexpectedChar {
+                        line: self.line_idx,
+                        col: self.col_idx,
+                        token: ch.to_string(),
+                    });
                     self.advance(1);
+                    if self.config.fail_fast {
+                        break;
+                    }
                 }
             }
         }
 
-        Ok(())
+        Ok(None)
     }
 
-    pub fn print_tokens(&self) {
+    /// Tokenize and return the first error found, if any.
+    ///
+    /// Unlike [`Lexer::tokenize`], scanning stops at the first unexpected character.
+    pub fn tokenize_strict(&mut self) -> AppResult<&[Token]> {
+        let fail_fast = std::mem::replace(&mut self.config.fail_fast, true);
+        let result = self.tokenize();
+        self.config.fail_fast = fail_fast;
+        result?;
+        match self.errors.first() {
+            Some(e) => Err(e.clone().into()),
+            None => Ok(&self.tokens),
+        }
+    }
+
+    pub fn print_tokens(&self, session: &mut Session) -> AppResult<()> {
         for token in self.tokens.iter() {
-            let (name, literal, value) = token.info();
-            println!("{} {} {}", name, literal, value.unwrap_or("null".into()))
+            writeln!(session.out, "{}", Self::token_line(token))?;
+        }
+        Ok(())
+    }
+
+    /// Print tokens as s-expressions.
+    ///
+    /// Each token is `(kind "lexeme" value)`, all wrapped in `(tokens ...)`.
+    pub fn print_sexp(&self, session: &mut Session) -> AppResult<()> {
+        let lines = self
+            .tokens
+            .iter()
+            .map(|token| {
+                let (name, literal, value) = token.info();
+                let value = match value {
+                    Some(v) if token.is_string() => Self::sexp_string(&v),
+                    Some(v) => v,
+                    None => "nil".into(),
+                };
+                format!("  ({} {} {})", name, Self::sexp_string(&literal), value)
+            })
+            .collect::<Vec<_>>();
+        writeln!(session.out, "(tokens\n{})", lines.join("\n"))?;
+        Ok(())
+    }
+
+    /// Print errors found in last tokenize.
+    pub fn print_errors(&self, session: &mut Session) -> AppResult<()> {
+        for error in self.errors.iter() {
+            session.report(error)?;
+        }
+        Ok(())
+    }
+
+    /// Compare produced tokens with `other`.
+    ///
+    /// Print the index and both renderings of the first different token, or `identical` if all
+    /// tokens are the same.
+    pub fn print_diff(&self, other: &Lexer, session: &mut Session) -> AppResult<()> {
+        let length = self.tokens.len().max(other.tokens.len());
+        for idx in 0..length {
+            let lhs = self.tokens.get(idx);
+            let rhs = other.tokens.get(idx);
+            if lhs != rhs {
+                let render = |t: Option<&Token>| t.map_or("EOF  null".into(), Self::token_line);
+                writeln!(session.out, "token {idx} differs:")?;
+                writeln!(session.out, "< {}", render(lhs))?;
+                writeln!(session.out, "> {}", render(rhs))?;
+                return Ok(());
+            }
         }
-        println!("EOF  null");
+        writeln!(session.out, "identical")?;
+        Ok(())
     }
 
     pub fn has_error(&self) -> bool {
-        self.has_error
+        !self.errors.is_empty()
+    }
+
+    pub fn errors(&self) -> &Vec<AppError> {
+        &self.errors
+    }
+
+    /// Count of lines scanned, the index of the line where scan ended.
+    pub fn line_count(&self) -> usize {
+        self.line_idx
+    }
+
+    /// Counts of lines, tokens and errors after [`Lexer::tokenize`].
+    pub fn stats(&self) -> LexerStats {
+        LexerStats {
+            lines: self.line_count(),
+            tokens: self.tokens.len(),
+            errors: self.errors.len(),
+        }
     }
 
     pub fn tokens(&self) -> &Vec<Token> {
         &self.tokens
     }
 
+    /// Line where each token in [`Lexer::tokens`] starts.
+    pub fn token_lines(&self) -> &Vec<usize> {
+        &self.token_lines
+    }
+
+    fn token_line(token: &Token) -> String {
+        let (name, literal, value) = token.info();
+        format!("{} {} {}", name, literal, value.unwrap_or("null".into()))
+    }
+
+    /// ANSI escape sequence to highlight `token`, `None` for the default color.
+    fn color_of(token: &Token) -> Option<&'static str> {
+        match token {
+            Token::Keyword(..) => Some("\x1b[35m"),
+            Token::String(..) => Some("\x1b[32m"),
+            Token::Number(..) => Some("\x1b[33m"),
+            Token::Ignored(IgnoredToken::Comment(..) | IgnoredToken::BlockComment(..)) => {
+                Some("\x1b[2m")
+            }
+            _ => None,
+        }
+    }
+
+    /// Quote `s` as an s-expression string.
+    fn sexp_string(s: &str) -> String {
+        let escaped = s
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+            .replace('\t', "\\t");
+        format!("\"{escaped}\"")
+    }
+
     fn ended(&self) -> bool {
         self.pos >= self.length
     }
@@ -102,5 +419,576 @@ impl Lexer {
 
     fn advance(&mut self, step: usize) {
         self.pos += step;
+        self.col_idx += step;
+    }
+
+    /// Advance over `step` characters of a token, following the line breaks inside.
+    fn skip(&mut self, step: usize) {
+        let pos = self.pos;
+        self.advance(step);
+        // Line breaks, block comments and strings may span lines.
+        let text = &self.input[pos..self.pos];
+        if let Some(last) = text.iter().rposition(|x| x == &'\n') {
+            self.line_idx += text.iter().filter(|x| x == &&'\n').count();
+            self.col_idx = text.len() - last;
+        }
+    }
+}
+
+impl Iterator for Lexer {
+    type Item = AppResult<Token>;
+
+    /// Produce tokens one at a time, without collecting them into [`Lexer::tokens`].
+    ///
+    /// The last item is always [`Token::Eof`], even after an error, then `None` follows. Unexpected
+    /// characters, invalid escapes and too long identifiers are recorded in [`Lexer::errors`] and
+    /// scanning goes on, other errors are yielded and stop scanning. Call [`Lexer::reset`] to
+    /// iterate again from the beginning.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        match self.step() {
+            Ok(Some((_, t))) => {
+                self.token_lines.push(self.token_line);
+                return Some(Ok(t));
+            }
+            Ok(None) => { /* Ended */ }
+            Err(e) => return Some(Err(e)),
+        }
+        self.finished = true;
+        self.token_lines.push(self.line_idx);
+        Some(Ok(Token::Eof))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::SharedBuffer;
+
+    /// Tokens in `input` as printed by `tokenize`.
+    fn printed(input: &str) -> Vec<String> {
+        let mut lexer = Lexer::new(input.to_string());
+        lexer.tokenize().unwrap();
+        lexer.tokens().iter().map(Lexer::token_line).collect()
+    }
+
+    /// Everything `f` writes to `out` of a session.
+    fn output_of(f: impl FnOnce(&mut Session) -> AppResult<()>) -> String {
+        let out = SharedBuffer::default();
+        let mut session = Session::new(Box::new(out.clone()), Box::new(std::io::sink()));
+        f(&mut session).unwrap();
+        out.contents()
+    }
+
+    fn first_error(input: &str) -> String {
+        let mut lexer = Lexer::new(input.to_string());
+        lexer.tokenize().unwrap();
+        lexer.errors()[0].to_string()
+    }
+
+    #[test]
+    fn identifier_length_limit() {
+        let config = LexerConfig {
+            max_identifier_length: Some(5),
+            ..Default::default()
+        };
+        let mut lexer = Lexer::new("abcde abcdef 2".to_string()).with_config(config.clone());
+        lexer.tokenize().unwrap();
+        let printed: Vec<_> = lexer.tokens().iter().map(Lexer::token_line).collect();
+        assert_eq!(
+            printed,
+            ["IDENTIFIER abcde null", "NUMBER 2 2.0", "EOF  null"]
+        );
+        let err = &lexer.errors()[0];
+        assert_eq!(
+            err.to_string(),
+            "[line 1, col 7] Error: Identifier too long."
+        );
+        assert_eq!(err.code(), "E0003");
+
+        let mut lexer = Lexer::new("abcde".to_string()).with_config(config);
+        assert!(lexer.tokenize().is_ok());
+        let mut lexer = Lexer::new("a".repeat(10_000));
+        assert!(lexer.tokenize().is_ok());
+    }
+
+    #[test]
+    fn operator_precedence_order() {
+        let mut lexer = Lexer::new("* + < == and or ! 1 x".to_string());
+        lexer.tokenize().unwrap();
+        let levels = lexer
+            .tokens()
+            .iter()
+            .map(Token::precedence)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            levels,
+            [
+                Some(5),
+                Some(4),
+                Some(3),
+                Some(2),
+                Some(1),
+                Some(0),
+                None,
+                None,
+                None,
+                None
+            ]
+        );
+        assert!(levels.windows(2).take(5).all(|x| x[0] > x[1]));
+    }
+
+    #[test]
+    fn multi_line_string_advances_line() {
+        assert_eq!(
+            first_error("\"a\nb\"\n@"),
+            "[line 3, col 1] Error: Unexpected character: @"
+        );
+        assert_eq!(
+            first_error("\"a\nbc\" @"),
+            "[line 2, col 5] Error: Unexpected character: @"
+        );
+    }
+
+    #[test]
+    fn tokenize_with_matches_tokens() {
+        let input = "var a = (1 + 2.5) * \"x\"; // comment\n@ and";
+        let mut streamed = vec![];
+        let mut lexer = Lexer::new(input.to_string());
+        lexer.tokenize_with(|t| streamed.push(t)).unwrap();
+        lexer.tokenize().unwrap();
+        assert_eq!(&streamed, lexer.tokens());
+        assert_eq!(streamed.last(), Some(&Token::Eof));
+    }
+
+    #[test]
+    fn iterator_ends_with_one_eof() {
+        let mut lexer = Lexer::new("1 +".to_string());
+        let tokens = lexer.by_ref().collect::<AppResult<Vec<_>>>().unwrap();
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(tokens.last(), Some(&Token::Eof));
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn iterator_yields_eof_after_error() {
+        let mut lexer = Lexer::new("1 \"abc".to_string());
+        assert!(matches!(lexer.next(), Some(Ok(Token::Number(..)))));
+        assert!(matches!(lexer.next(), Some(Err(..))));
+        assert!(matches!(lexer.next(), Some(Ok(Token::Eof))));
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn block_comment_advances_line() {
+        assert_eq!(
+            first_error("/* a\n b */ @"),
+            "[line 2, col 7] Error: Unexpected character: @"
+        );
+    }
+
+    #[test]
+    fn tokenize_strict_stops_at_first_error() {
+        let input = "1 @ 2 # 3";
+        let mut lexer = Lexer::new(input.to_string());
+        assert_eq!(
+            lexer.tokenize_strict().unwrap_err().to_string(),
+            "[line 1, col 3] Error: Unexpected character: @"
+        );
+        assert_eq!(lexer.errors().len(), 1);
+
+        let mut lexer = Lexer::new(input.to_string());
+        lexer.tokenize().unwrap();
+        assert_eq!(lexer.errors().len(), 2);
+        assert_eq!(lexer.tokens().len(), 4);
+
+        let mut lexer = Lexer::new("1 + 2".to_string());
+        assert_eq!(lexer.tokenize_strict().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn keyword_prefix_is_identifier() {
+        assert_eq!(
+            printed("orchid classy return_value andy for"),
+            [
+                "IDENTIFIER orchid null",
+                "IDENTIFIER classy null",
+                "IDENTIFIER return_value null",
+                "IDENTIFIER andy null",
+                "FOR for null",
+                "EOF  null",
+            ]
+        );
+    }
+
+    #[test]
+    fn number_literal_trims_trailing_zeros() {
+        assert_eq!(
+            printed("200.00 51.250 1000000.0 5"),
+            [
+                "NUMBER 200.00 200.0",
+                "NUMBER 51.250 51.25",
+                "NUMBER 1000000.0 1000000.0",
+                "NUMBER 5 5.0",
+                "EOF  null",
+            ]
+        );
+    }
+
+    #[test]
+    fn number_with_dots() {
+        assert_eq!(
+            printed("123."),
+            ["NUMBER 123 123.0", "DOT . null", "EOF  null"]
+        );
+        assert_eq!(
+            printed(".123"),
+            ["DOT . null", "NUMBER 123 123.0", "EOF  null"]
+        );
+        assert_eq!(
+            printed("123.456.789"),
+            [
+                "NUMBER 123.456 123.456",
+                "DOT . null",
+                "NUMBER 789 789.0",
+                "EOF  null"
+            ]
+        );
+        assert_eq!(
+            printed("1..2"),
+            [
+                "NUMBER 1 1.0",
+                "DOT . null",
+                "DOT . null",
+                "NUMBER 2 2.0",
+                "EOF  null"
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_escape_keeps_scanning() {
+        let mut lexer = Lexer::new(r#"1 "\q" 2 @"#.to_string());
+        lexer.tokenize().unwrap();
+        let printed: Vec<_> = lexer.tokens().iter().map(Lexer::token_line).collect();
+        assert_eq!(printed, ["NUMBER 1 1.0", "NUMBER 2 2.0", "EOF  null"]);
+        let errors: Vec<_> = lexer.errors().iter().map(|x| x.to_string()).collect();
+        assert_eq!(
+            errors,
+            [
+                r"[line 1, col 4] Error: Invalid escape sequence: \q",
+                "[line 1, col 10] Error: Unexpected character: @",
+            ]
+        );
+
+        assert_eq!(
+            first_error("\"a\nb\\x4\""),
+            r"[line 2, col 2] Error: Invalid escape sequence: \x4"
+        );
+    }
+
+    #[test]
+    fn decimal_number_value() {
+        assert_eq!(
+            printed("3.14 0.5 100.00 42"),
+            [
+                "NUMBER 3.14 3.14",
+                "NUMBER 0.5 0.5",
+                "NUMBER 100.00 100.0",
+                "NUMBER 42 42.0",
+                "EOF  null",
+            ]
+        );
+    }
+
+    #[test]
+    fn large_integer_literal() {
+        assert_eq!(
+            printed("123456789012 0 00123"),
+            [
+                "NUMBER 123456789012 123456789012.0",
+                "NUMBER 0 0.0",
+                "NUMBER 00123 123.0",
+                "EOF  null",
+            ]
+        );
+    }
+
+    #[test]
+    fn scientific_notation() {
+        assert_eq!(
+            printed("1e3 6.022e23 1E-5"),
+            [
+                "NUMBER 1e3 1000.0",
+                "NUMBER 6.022e23 602200000000000027262976.0",
+                "NUMBER 1E-5 0.00001",
+                "EOF  null",
+            ]
+        );
+        // Without digits the exponent is not part of the number.
+        assert_eq!(
+            printed("1e"),
+            ["NUMBER 1 1.0", "IDENTIFIER e null", "EOF  null"]
+        );
+    }
+
+    #[test]
+    fn crlf_line_endings() {
+        let mut lexer = Lexer::new("var x;\r\n var y;".to_string());
+        lexer.tokenize().unwrap();
+        assert!(!lexer.has_error());
+        assert_eq!(lexer.tokens().len(), 7);
+        assert_eq!(*lexer.token_lines(), [1, 1, 1, 2, 2, 2, 2]);
+    }
+
+    #[test]
+    fn unicode_identifiers() {
+        assert_eq!(
+            printed("π naïve _private"),
+            [
+                "IDENTIFIER π null",
+                "IDENTIFIER naïve null",
+                "IDENTIFIER _private null",
+                "EOF  null",
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_block_comments() {
+        assert_eq!(
+            printed("1 /* a /* b /* c */ b */\n a */ 2"),
+            ["NUMBER 1 1.0", "NUMBER 2 2.0", "EOF  null"]
+        );
+        assert_eq!(
+            first_error("/* a /* b /* c */ b */\n a */ @"),
+            "[line 2, col 7] Error: Unexpected character: @"
+        );
+
+        // The outer comment is still open after the inner one ends.
+        let mut lexer = Lexer::new("1 /* a /* b */ 2".to_string());
+        let err = lexer.tokenize().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "[line 1, col 3] Error: Unterminated block comment."
+        );
+        assert_eq!(
+            err.downcast_ref::<AppError>().map(AppError::code),
+            Some("E0010")
+        );
+    }
+
+    #[test]
+    fn hex_escape() {
+        assert_eq!(printed(r#""\x41""#), [r#"STRING "\x41" A"#, "EOF  null"]);
+        assert_eq!(
+            first_error(r#""\xG0""#),
+            r"[line 1, col 2] Error: Invalid escape sequence: \xG0"
+        );
+    }
+
+    #[test]
+    fn crlf_in_string() {
+        let string_value = |normalize: bool| {
+            let config = LexerConfig {
+                normalize_newlines_in_strings: normalize,
+                ..Default::default()
+            };
+            let mut lexer = Lexer::new("\"a\r\nb\"".to_string()).with_config(config);
+            lexer.tokenize().unwrap();
+            match &lexer.tokens()[0] {
+                Token::String(v) => (v.value.clone(), lexer.token_lines()[1]),
+                v => panic!("expected a string, got {v:?}"),
+            }
+        };
+        assert_eq!(string_value(false), ("a\r\nb".to_string(), 2));
+        assert_eq!(string_value(true), ("a\nb".to_string(), 2));
+    }
+
+    #[test]
+    fn leading_bom_is_skipped() {
+        let input = "var a = 1;\n@";
+        let mut plain = Lexer::new(input.to_string());
+        plain.tokenize().unwrap();
+        let mut with_bom = Lexer::new(format!("\u{FEFF}{input}"));
+        with_bom.tokenize().unwrap();
+        assert_eq!(with_bom.tokens(), plain.tokens());
+        assert_eq!(with_bom.token_lines(), plain.token_lines());
+        assert_eq!(
+            with_bom.errors()[0].to_string(),
+            plain.errors()[0].to_string()
+        );
+
+        assert_eq!(
+            first_error("1 \u{FEFF}"),
+            "[line 1, col 3] Error: Unexpected character: \u{FEFF}"
+        );
+    }
+
+    #[test]
+    fn keep_whitespace_tokens() {
+        let config = LexerConfig {
+            keep_whitespace: true,
+            ..Default::default()
+        };
+        let mut lexer = Lexer::new("1 // c\n2".to_string()).with_config(config);
+        lexer.tokenize().unwrap();
+        let kept: Vec<_> = lexer.tokens().iter().map(Lexer::token_line).collect();
+        assert_eq!(
+            kept,
+            [
+                "NUMBER 1 1.0",
+                "WHITESPACE   null",
+                "COMMENT // c null",
+                r"NEWLINE \n null",
+                "NUMBER 2 2.0",
+                "EOF  null",
+            ]
+        );
+        assert_eq!(
+            printed("1 // c\n2"),
+            ["NUMBER 1 1.0", "NUMBER 2 2.0", "EOF  null"]
+        );
+    }
+
+    #[test]
+    fn configured_keyword_set() {
+        let mut config = LexerConfig::default();
+        assert!(config.keywords.remove("print"));
+        let mut lexer = Lexer::new("print var".to_string()).with_config(config);
+        lexer.tokenize().unwrap();
+        let printed: Vec<_> = lexer.tokens().iter().map(Lexer::token_line).collect();
+        assert_eq!(
+            printed,
+            ["IDENTIFIER print null", "VAR var null", "EOF  null"]
+        );
+    }
+
+    #[test]
+    fn literal_tokens() {
+        let mut lexer = Lexer::new("12.5 \"ab\" true nil print +".to_string());
+        lexer.tokenize().unwrap();
+        let values: Vec<_> = lexer
+            .tokens()
+            .iter()
+            .map(|x| {
+                let value = x.literal_value().map(|v| (v.type_name(), v.literal()));
+                assert_eq!(x.is_literal(), value.is_some());
+                value
+            })
+            .collect();
+        assert_eq!(
+            values,
+            [
+                Some(("number", "12.5".to_string())),
+                Some(("string", "ab".to_string())),
+                Some(("boolean", "true".to_string())),
+                Some(("nil", "nil".to_string())),
+                None,
+                None,
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_again() {
+        let mut lexer = Lexer::new("var a = 1;\n@ \"b\"".to_string());
+        lexer.tokenize().unwrap();
+        let (tokens, lines) = (lexer.tokens().clone(), lexer.token_lines().clone());
+        lexer.tokenize().unwrap();
+        assert_eq!(*lexer.tokens(), tokens);
+        assert_eq!(*lexer.token_lines(), lines);
+        assert_eq!(lexer.errors().len(), 1);
+        assert_eq!(lexer.line_count(), 2);
+
+        lexer.retokenize("1\n\n2".to_string()).unwrap();
+        let mut fresh = Lexer::new("1\n\n2".to_string());
+        fresh.tokenize().unwrap();
+        assert_eq!(lexer.tokens(), fresh.tokens());
+        assert_eq!(lexer.token_lines(), fresh.token_lines());
+        assert!(!lexer.has_error());
+        assert_eq!(lexer.line_count(), 3);
+    }
+
+    #[test]
+    fn stats() {
+        let mut lexer = Lexer::new("var a = 1;\n@ # \"b\"\n".to_string());
+        lexer.tokenize().unwrap();
+        let stats = lexer.stats();
+        assert_eq!(
+            stats,
+            LexerStats {
+                lines: 3,
+                tokens: 7,
+                errors: 2,
+            }
+        );
+        assert_eq!(stats.to_string(), "lines: 3, tokens: 7, errors: 2");
+    }
+
+    #[test]
+    fn sexp_output() {
+        let mut lexer = Lexer::new("x = \"a\\\"b\" 12.50".to_string());
+        lexer.tokenize().unwrap();
+        let expected = [
+            "(tokens",
+            "  (IDENTIFIER \"x\" nil)",
+            "  (EQUAL \"=\" nil)",
+            r#"  (STRING "\"a\\\"b\"" "a\"b")"#,
+            "  (NUMBER \"12.50\" 12.5)",
+            "  (EOF \"\" nil))",
+            "",
+        ];
+        assert_eq!(output_of(|s| lexer.print_sexp(s)), expected.join("\n"));
+    }
+
+    #[test]
+    fn diff_output() {
+        let lexer = |input: &str| {
+            let mut lexer = Lexer::new(input.to_string());
+            lexer.tokenize().unwrap();
+            lexer
+        };
+        let (lhs, rhs) = (lexer("var a = 1;"), lexer("var b = 1;"));
+        assert_eq!(
+            output_of(|s| lhs.print_diff(&rhs, s)),
+            "token 1 differs:\n< IDENTIFIER a null\n> IDENTIFIER b null\n"
+        );
+        assert_eq!(output_of(|s| lhs.print_diff(&lhs, s)), "identical\n");
+    }
+
+    #[test]
+    fn highlighted_output() {
+        let input = "var a = \"s\" @ 1; // c";
+        let mut lexer = Lexer::new(input.to_string());
+        assert_eq!(
+            output_of(|s| lexer.print_highlighted(s, true)),
+            "\x1b[35mvar\x1b[0m a = \x1b[32m\"s\"\x1b[0m @ \x1b[33m1\x1b[0m; \x1b[2m// c\x1b[0m"
+        );
+        assert_eq!(lexer.errors().len(), 1);
+
+        let mut lexer = Lexer::new(input.to_string());
+        assert_eq!(output_of(|s| lexer.print_highlighted(s, false)), input);
+        assert_eq!(lexer.errors().len(), 1);
+    }
+
+    #[test]
+    fn fail_fast_reports_first_error() {
+        let config = LexerConfig {
+            fail_fast: true,
+            ..Default::default()
+        };
+        let mut lexer = Lexer::new("1 @ 2 # \"\\q\"".to_string()).with_config(config);
+        lexer.tokenize().unwrap();
+        let errors: Vec<_> = lexer.errors().iter().map(|x| x.to_string()).collect();
+        assert_eq!(errors, ["[line 1, col 3] Error: Unexpected character: @"]);
+
+        let mut lexer = Lexer::new("1 @ 2 # \"\\q\"".to_string());
+        lexer.tokenize().unwrap();
+        assert_eq!(lexer.errors().len(), 3);
     }
 }
@@ -1,11 +1,16 @@
+use std::collections::HashMap;
+
 use crate::errors::{AppError, AppResult};
+use crate::parser::expr::Value;
+
+use super::LexerConfig;
 
 pub(super) trait Tokened: Sized {
     /// Get the token info.
     fn info(&self) -> (&'static str, String, Option<String>);
 
     /// Try parse from character.
-    fn from_char_slice(s: &[char], line: usize) -> AppResult<Option<Self>>;
+    fn from_char_slice(s: &[char], line: usize, col: usize) -> AppResult<Option<Self>>;
 
     /// Get the characters count of current token.
     fn length(&self) -> usize;
@@ -20,53 +25,90 @@ pub enum Token {
     Number(NumberToken),
     Identifier(IdentifierToken),
     Keyword(KeywordToken),
+
+    /// End of input, the last token produced by the lexer.
+    Eof,
+}
+
+/// What [`Token::try_consume`] found at the start of input.
+#[derive(Debug)]
+pub(super) enum Consumed {
+    /// A valid token.
+    Token(Token),
+
+    /// A complete but invalid token, `length` characters to skip after recording `error`.
+    Rejected { error: AppError, length: usize },
 }
 
 impl Token {
-    pub(super) fn info(&self) -> (&'static str, String, Option<String>) {
+    /// Name, lexeme and literal value of the token.
+    pub fn info(&self) -> (&'static str, String, Option<String>) {
         match self {
             Token::SingleCharacter(t) => t.info(),
             Token::MultiCharToken(t) => t.info(),
-            Token::Ignored(_) => unreachable!("no info provided on ignored tokens"),
+            Token::Ignored(t) => t.info(),
             Token::String(t) => t.info(),
             Token::Number(t) => t.info(),
             Token::Identifier(t) => t.info(),
             Token::Keyword(t) => t.info(),
+            Token::Eof => ("EOF", "".into(), None),
         }
     }
 
-    pub(super) fn try_consume(s: &[char], line: usize) -> AppResult<Option<Self>> {
+    pub(super) fn try_consume(
+        s: &[char],
+        line: usize,
+        col: usize,
+        config: &LexerConfig,
+    ) -> AppResult<Option<Consumed>> {
         if s.is_empty() {
             return Ok(None);
         }
 
-        if let Some(v) = KeywordToken::from_char_slice(s, line)? {
-            return Ok(Some(Self::Keyword(v)));
+        if let Some(v) = config.keywords.match_char_slice(s, line, col)? {
+            return Ok(Some(Consumed::Token(Self::Keyword(v))));
         }
 
-        if let Some(v) = StringToken::from_char_slice(s, line)? {
-            return Ok(Some(Self::String(v)));
+        match StringToken::from_char_slice(s, line, col) {
+            Ok(Some(v)) if config.normalize_newlines_in_strings => {
+                return Ok(Some(Consumed::Token(Self::String(v.normalize_newlines()))));
+            }
+            Ok(Some(v)) => return Ok(Some(Consumed::Token(Self::String(v)))),
+            Ok(None) => {}
+            Err(e) => match e.downcast::<AppError>() {
+                Ok(error @ AppError::InvalidEscape { .. }) => {
+                    // The closing quote is always found when decoding escapes.
+                    let length = StringToken::closing_quote(s).map_or(s.len(), |x| x + 1);
+                    return Ok(Some(Consumed::Rejected { error, length }));
+                }
+                Ok(error) => return Err(error.into()),
+                Err(e) => return Err(e),
+            },
         }
 
-        if let Some(v) = NumberToken::from_char_slice(s, line)? {
-            return Ok(Some(Self::Number(v)));
+        if let Some(v) = NumberToken::from_char_slice(s, line, col)? {
+            return Ok(Some(Consumed::Token(Self::Number(v))));
         }
 
-        if let Some(v) = IdentifierToken::from_char_slice(s, line)? {
-            return Ok(Some(Self::Identifier(v)));
+        if let Some(v) = IdentifierToken::from_char_slice(s, line, col)? {
+            if let Err(error) = v.check_length(config.max_identifier_length, line, col) {
+                let length = v.length();
+                return Ok(Some(Consumed::Rejected { error, length }));
+            }
+            return Ok(Some(Consumed::Token(Self::Identifier(v))));
         }
 
         // Multi characters
-        if let Some(v) = MultiCharToken::from_char_slice(s, line)? {
-            return Ok(Some(Self::MultiCharToken(v)));
+        if let Some(v) = MultiCharToken::from_char_slice(s, line, col)? {
+            return Ok(Some(Consumed::Token(Self::MultiCharToken(v))));
         }
 
-        if let Some(v) = IgnoredToken::from_char_slice(s, line)? {
-            return Ok(Some(Self::Ignored(v)));
+        if let Some(v) = IgnoredToken::from_char_slice(s, line, col)? {
+            return Ok(Some(Consumed::Token(Self::Ignored(v))));
         }
 
-        if let Some(v) = SingleCharToken::from_char_slice(s, line)? {
-            return Ok(Some(Self::SingleCharacter(v)));
+        if let Some(v) = SingleCharToken::from_char_slice(s, line, col)? {
+            return Ok(Some(Consumed::Token(Self::SingleCharacter(v))));
         }
 
         Ok(None)
@@ -82,6 +124,7 @@ impl Token {
             Token::Number(..) => false,
             Token::Identifier(..) => false,
             Token::Keyword(..) => false,
+            Token::Eof => false,
         }
     }
 
@@ -94,29 +137,83 @@ impl Token {
             Token::Number(v) => v.length(),
             Token::Identifier(v) => v.length(),
             Token::Keyword(v) => v.length(),
+            Token::Eof => 0,
         }
     }
 
     pub fn is_string(&self) -> bool {
-        if let Token::String(..) = &self {
-            true
-        } else {
-            false
-        }
+        matches!(self, Token::String(..))
     }
 
     pub fn is_number(&self) -> bool {
-        if let Token::Number(..) = &self {
-            true
-        } else {
-            false
-        }
+        matches!(self, Token::Number(..))
     }
 
     pub fn is_string_or_number(&self) -> bool {
         self.is_string() || self.is_number()
     }
 
+    /// Is a string, number, `true`, `false` or `nil`.
+    pub fn is_literal(&self) -> bool {
+        self.is_string_or_number()
+            || matches!(
+                self,
+                Token::Keyword(KeywordToken::KTrue | KeywordToken::KFalse | KeywordToken::KNil)
+            )
+    }
+
+    /// The value of a literal token, `None` if not a literal.
+    pub fn literal_value(&self) -> Option<Value> {
+        if self.is_literal() {
+            Value::try_from(self).ok()
+        } else {
+            None
+        }
+    }
+
+    /// Get the precedence level of the operator token.
+    ///
+    /// Higher level binds tighter:
+    ///
+    /// * 5: factor, `*` `/`
+    /// * 4: term, `+` `-`
+    /// * 3: comparison, `<` `<=` `>` `>=`
+    /// * 2: equality, `==` `!=`
+    /// * 1: logical and, `and`
+    /// * 0: logical or, `or`
+    ///
+    /// `-` is treated as the binary minus here. Return `None` if the token is not an operator.
+    pub fn precedence(&self) -> Option<u8> {
+        match self {
+            Token::SingleCharacter(v) => match v {
+                SingleCharToken::Star | SingleCharToken::Slash => Some(5),
+                SingleCharToken::Plus | SingleCharToken::Minus => Some(4),
+                SingleCharToken::Less | SingleCharToken::Greater => Some(3),
+                SingleCharToken::LeftParen
+                | SingleCharToken::RightParen
+                | SingleCharToken::LeftBrace
+                | SingleCharToken::RightBrace
+                | SingleCharToken::Dot
+                | SingleCharToken::Comma
+                | SingleCharToken::Semicolon
+                | SingleCharToken::Assign
+                | SingleCharToken::Bang => None,
+            },
+            Token::MultiCharToken(v) => match v {
+                MultiCharToken::LessEqual | MultiCharToken::GreaterEqual => Some(3),
+                MultiCharToken::EqualEqual | MultiCharToken::BangEqual => Some(2),
+            },
+            Token::Keyword(KeywordToken::KAnd) => Some(1),
+            Token::Keyword(KeywordToken::KOr) => Some(0),
+            Token::Ignored(..)
+            | Token::String(..)
+            | Token::Number(..)
+            | Token::Identifier(..)
+            | Token::Keyword(..)
+            | Token::Eof => None,
+        }
+    }
+
     pub fn is_binary_op(&self) -> bool {
         match self {
             Token::SingleCharacter(v) => match v {
@@ -142,6 +239,7 @@ impl Token {
             Token::Number(..) => false,
             Token::Identifier(..) => false,
             Token::Keyword(..) => false,
+            Token::Eof => false,
         }
     }
 
@@ -170,6 +268,7 @@ impl Token {
             Token::Number(..) => false,
             Token::Identifier(..) => false,
             Token::Keyword(..) => false,
+            Token::Eof => false,
         }
     }
 }
@@ -243,8 +342,8 @@ impl Tokened for SingleCharToken {
         }
     }
 
-    fn from_char_slice(s: &[char], _: usize) -> AppResult<Option<Self>> {
-        let ch = match s.get(0) {
+    fn from_char_slice(s: &[char], _: usize, _: usize) -> AppResult<Option<Self>> {
+        let ch = match s.first() {
             Some(v) => v,
             None => return Ok(None),
         };
@@ -283,8 +382,18 @@ pub enum IgnoredToken {
     /// `//`
     ///
     ///
-    /// Holding the comment length till the end of current line.
-    Comment(usize),
+    /// Holding the comment text till the end of current line.
+    Comment(String),
+
+    /// `/* */`
+    ///
+    /// Holding the comment text including both delimiters, may cross lines and nest.
+    BlockComment(String),
+
+    /// `\r`
+    ///
+    /// Part of the CRLF line ending, the line is counted by the following `\n`.
+    CarriageReturn,
 
     /// `\t`
     Tab,
@@ -294,18 +403,49 @@ pub enum IgnoredToken {
 }
 
 impl Tokened for IgnoredToken {
+    /// Ignored tokens only show up when the lexer keeps whitespace.
+    ///
+    /// Line break, carriage return and tab are escaped so that each token still prints on its own line.
     fn info(&self) -> (&'static str, String, Option<String>) {
-        unreachable!("no info provided on ignored tokens")
+        match self {
+            IgnoredToken::LineBreak => ("NEWLINE", "\\n".into(), None),
+            IgnoredToken::Comment(v) => ("COMMENT", v.clone(), None),
+            IgnoredToken::BlockComment(v) => ("COMMENT", v.replace('\n', "\\n"), None),
+            IgnoredToken::CarriageReturn => ("WHITESPACE", "\\r".into(), None),
+            IgnoredToken::Tab => ("WHITESPACE", "\\t".into(), None),
+            IgnoredToken::Space => ("WHITESPACE", " ".into(), None),
+        }
     }
 
-    fn from_char_slice(s: &[char], _: usize) -> AppResult<Option<Self>> {
-        let ret = match (s.get(0), s.get(1)) {
+    fn from_char_slice(s: &[char], line: usize, col: usize) -> AppResult<Option<Self>> {
+        let ret = match (s.first(), s.get(1)) {
             (Some('\n'), _) => Some(Self::LineBreak),
+            (Some('\r'), _) => Some(Self::CarriageReturn),
             (Some('\t'), _) => Some(Self::Tab),
             (Some(' '), _) => Some(Self::Space),
             (Some('/'), Some('/')) => Some(Self::Comment(
-                s.iter().position(|x| x == &'\n').unwrap_or_else(|| s.len()),
+                s.iter().take_while(|x| x != &&'\n').collect(),
             )),
+            (Some('/'), Some('*')) => {
+                // Block comments nest, find the `*/` closing the outermost one.
+                let mut depth = 1;
+                let mut idx = 2;
+                while depth > 0 {
+                    match (s.get(idx), s.get(idx + 1)) {
+                        (Some('/'), Some('*')) => {
+                            depth += 1;
+                            idx += 2;
+                        }
+                        (Some('*'), Some('/')) => {
+                            depth -= 1;
+                            idx += 2;
+                        }
+                        (Some(_), _) => idx += 1,
+                        (None, _) => return Err(AppError::UnterminatedComment { line, col }.into()),
+                    }
+                }
+                Some(Self::BlockComment(s[..idx].iter().collect()))
+            }
             _ => None,
         };
 
@@ -315,13 +455,16 @@ impl Tokened for IgnoredToken {
     fn length(&self) -> usize {
         match self {
             IgnoredToken::LineBreak => 1,
-            IgnoredToken::Comment(len) => len.to_owned(),
+            IgnoredToken::Comment(v) => v.chars().count(),
+            IgnoredToken::BlockComment(v) => v.chars().count(),
+            IgnoredToken::CarriageReturn => 1,
             IgnoredToken::Tab => 1,
             IgnoredToken::Space => 1,
         }
     }
 }
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum MultiCharToken {
     /// `==`
@@ -347,8 +490,8 @@ impl Tokened for MultiCharToken {
         }
     }
 
-    fn from_char_slice(s: &[char], _: usize) -> AppResult<Option<Self>> {
-        let ret = match (s.get(0), s.get(1)) {
+    fn from_char_slice(s: &[char], _: usize, _: usize) -> AppResult<Option<Self>> {
+        let ret = match (s.first(), s.get(1)) {
             (Some('='), Some('=')) => Some(Self::EqualEqual),
             (Some('!'), Some('=')) => Some(Self::BangEqual),
             (Some('<'), Some('=')) => Some(Self::LessEqual),
@@ -366,34 +509,134 @@ impl Tokened for MultiCharToken {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct StringToken(pub String);
+pub struct StringToken {
+    /// The original characters between the quotes.
+    lexeme: String,
+
+    /// The string value.
+    pub value: String,
+}
+
+impl StringToken {
+    /// Decode escape sequences in the characters between quotes.
+    ///
+    /// Supported escapes:
+    ///
+    /// * `\xNN`: the character of two hex digits, in range 0-255.
+    /// * `\n`, `\t`: line feed and tab.
+    /// * `\\`, `\"`: the backslash and quote themselves.
+    ///
+    /// Any other character after a backslash is an invalid escape, reported at the backslash.
+    /// `line` and `col` are the position of the opening quote.
+    fn decode_escapes(raw: &[char], line: usize, col: usize) -> AppResult<String> {
+        // Position of `raw[idx]`, strings may span lines.
+        let position = |idx: usize| match raw[..idx].iter().rposition(|x| x == &'\n') {
+            Some(last) => (
+                line + raw[..idx].iter().filter(|x| x == &&'\n').count(),
+                idx - last,
+            ),
+            None => (line, col + 1 + idx),
+        };
+        let mut value = String::new();
+        let mut idx = 0;
+        while idx < raw.len() {
+            match (raw[idx], raw.get(idx + 1)) {
+                ('\\', Some('x')) => {
+                    let seq_end = (idx + 4).min(raw.len());
+                    let ch = raw
+                        .get(idx + 2..idx + 4)
+                        .filter(|x| x.iter().all(|c| c.is_ascii_hexdigit()))
+                        .and_then(|x| u32::from_str_radix(&x.iter().collect::<String>(), 16).ok())
+                        .and_then(char::from_u32);
+                    match ch {
+                        Some(v) => value.push(v),
+                        None => {
+                            let (line, col) = position(idx);
+                            return Err(AppError::InvalidEscape {
+                                line,
+                                col,
+                                seq: raw[idx..seq_end].iter().collect(),
+                            }
+                            .into());
+                        }
+                    }
+                    idx = seq_end;
+                }
+                ('\\', Some(v @ ('n' | 't' | '\\' | '"'))) => {
+                    value.push(match v {
+                        'n' => '\n',
+                        't' => '\t',
+                        v => *v,
+                    });
+                    idx += 2;
+                }
+                ('\\', v) => {
+                    let (line, col) = position(idx);
+                    return Err(AppError::InvalidEscape {
+                        line,
+                        col,
+                        seq: std::iter::once('\\').chain(v.copied()).collect(),
+                    }
+                    .into());
+                }
+                (ch, _) => {
+                    value.push(ch);
+                    idx += 1;
+                }
+            }
+        }
+        Ok(value)
+    }
+
+    /// Index of the first quote after the opening one in `s` not escaped by a backslash.
+    fn closing_quote(s: &[char]) -> Option<usize> {
+        let mut idx = 1;
+        while idx < s.len() {
+            match s[idx] {
+                '\\' => idx += 2,
+                '"' => return Some(idx),
+                _ => idx += 1,
+            }
+        }
+        None
+    }
+
+    /// Convert CRLF and CR in value to LF, the lexeme is kept as it is.
+    fn normalize_newlines(mut self) -> Self {
+        self.value = self.value.replace("\r\n", "\n").replace('\r', "\n");
+        self
+    }
+}
 
 impl Tokened for StringToken {
     fn info(&self) -> (&'static str, String, Option<String>) {
-        ("STRING", format!(r#""{}""#, self.0), Some(self.0.clone()))
+        (
+            "STRING",
+            format!(r#""{}""#, self.lexeme),
+            Some(self.value.clone()),
+        )
     }
 
-    fn from_char_slice(s: &[char], line: usize) -> AppResult<Option<Self>> {
-        match s.get(0) {
+    fn from_char_slice(s: &[char], line: usize, col: usize) -> AppResult<Option<Self>> {
+        match s.first() {
             Some(v) if v == &'"' => { /* Matched */ }
             Some(_) => return Ok(None),
             None => return Ok(None),
         }
 
-        let end_pos = match s.iter().skip(1).position(|x| x == &'"') {
-            Some(v) => v + 1,
-            None => return Err(AppError::UnterminatedString { line }.into()),
+        let end_pos = match Self::closing_quote(s) {
+            Some(v) => v,
+            None => return Err(AppError::UnterminatedString { line, col }.into()),
         };
 
-        // Lox does not support escapting `"` in strings.
-
-        Ok(Some(StringToken(
-            s[1..end_pos].iter().map(|x| x.to_owned()).collect(),
-        )))
+        Ok(Some(StringToken {
+            value: Self::decode_escapes(&s[1..end_pos], line, col)?,
+            lexeme: s[1..end_pos].iter().collect(),
+        }))
     }
 
     fn length(&self) -> usize {
-        self.0.len() + 2
+        self.lexeme.chars().count() + 2
     }
 }
 
@@ -403,28 +646,38 @@ impl Tokened for StringToken {
 ///
 /// * 123
 /// * 123.0
+/// * 1e3, 6.022e23, 1E-5
 ///
 /// Invalid formats:
 ///
 /// * .123 => parse started after the `.`
 /// * 123. => parse finshed before the `.`
+///
+/// A `.` only belongs to the number when followed by a digit, and at most one `.` is taken:
+///
+/// * 123.456.789 => `123.456`, `.`, `789`
+/// * 1..2 => `1`, `.`, `.`, `2`
+///
+/// An exponent without digits is not part of the number:
+///
+/// * 1e => `1`, `e`
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct NumberToken {
-    integer: u32,
-
-    /// The length of integer part.
-    integer_length: usize,
-
-    /// The deciaml part contains of the original value `string` and calculated number value `usize`.
-    decimal: Option<(String, u32)>,
+    /// The original characters.
+    lexeme: String,
 }
 
 impl NumberToken {
+    /// Lox numbers are all doubles, parse the whole lexeme as `f64`.
     pub fn as_f64(&self) -> f64 {
-        match &self.decimal {
-            Some((l, f)) => self.integer as f64 + (f.to_owned() as f64 / l.len() as f64),
-            None => self.integer as f64,
-        }
+        // The lexeme only contains digits with an optional `.` between digits and an optional
+        // exponent, always valid.
+        self.lexeme.parse().unwrap_or_default()
+    }
+
+    /// Written without decimal part or exponent.
+    pub fn is_integer(&self) -> bool {
+        !self.lexeme.contains(['.', 'e', 'E'])
     }
 
     pub fn info_string(&self) -> String {
@@ -433,139 +686,108 @@ impl NumberToken {
 }
 
 impl Tokened for NumberToken {
+    /// The value is printed as the shortest form of the number, but always with a decimal part:
+    /// `1.25` is `1.25`, `10` and `10.00` are `10.0`.
     fn info(&self) -> (&'static str, String, Option<String>) {
-        let mut value = self.integer.to_string();
-        match &self.decimal {
-            Some(v) => value.push_str(format!(".{}", v.0).as_str()),
-            None => { /* Do nothing*/ }
-        }
-
-        let r = match &self.decimal {
-            Some(v) => {
-                if v.1 == 0 {
-                    format!("{}.{}", self.integer, v.1)
-                } else {
-                    format!(
-                        "{}.{}",
-                        self.integer,
-                        v.1.to_string().trim_end_matches(['0'])
-                    )
-                }
-            }
-            None => format!("{}.0", self.integer),
+        let value = self.as_f64();
+        let r = if value.fract() == 0.0 {
+            format!("{value:.1}")
+        } else {
+            value.to_string()
         };
 
-        ("NUMBER", value, Some(r))
+        ("NUMBER", self.lexeme.clone(), Some(r))
     }
 
-    fn from_char_slice(s: &[char], _: usize) -> AppResult<Option<Self>> {
-        if s.is_empty() || !s[0].is_digit(10) {
+    fn from_char_slice(s: &[char], _: usize, _: usize) -> AppResult<Option<Self>> {
+        if s.is_empty() || !s[0].is_ascii_digit() {
             return Ok(None);
         }
 
         // The first character is integer.
-        // Parse the integer part.
-        let raw_integer_chars = s
-            .iter()
-            .take_while(|x| x.is_digit(10))
-            .map(|x| x.to_digit(10).unwrap())
-            .collect::<Vec<_>>();
-        let integer = raw_integer_chars
-            .iter()
-            .rev()
-            .enumerate()
-            .map(|(idx, i)| i * 10_u32.pow(idx as u32))
-            .fold(0, |acc, x| acc + x);
-        let mut decimal_it = s.iter().skip(raw_integer_chars.len());
-        match decimal_it.next() {
-            Some(v) => {
-                if v == &'.' {
-                    // May have decimal parts.
-                    let raw_decimal_chars = decimal_it
-                        .take_while(|x| x.is_digit(10))
-                        .map(|x| x.to_digit(10).unwrap())
-                        .collect::<Vec<_>>();
-                    if raw_decimal_chars.is_empty() {
-                        // No decimal part, the `.` after integer part is another token.
-                        return Ok(Some(NumberToken {
-                            integer,
-                            integer_length: raw_integer_chars.len(),
-                            decimal: None,
-                        }));
-                    } else {
-                        // Have deciaml part. the `.` after integer part is part of number.
-                        let decimal_value = raw_decimal_chars
-                            .iter()
-                            .rev()
-                            .enumerate()
-                            .map(|(idx, i)| i * 10_u32.pow(idx as u32))
-                            .fold(0, |acc, x| acc + x);
-                        let decimal_string = raw_decimal_chars
-                            .iter()
-                            .map(|x| x.to_string())
-                            .collect::<String>();
-
-                        return Ok(Some(NumberToken {
-                            integer,
-                            integer_length: raw_integer_chars.len(),
-                            decimal: Some((decimal_string, decimal_value)),
-                        }));
-                    }
-                } else {
-                    // Only have integer part.
-                    return Ok(Some(NumberToken {
-                        integer,
-                        integer_length: raw_integer_chars.len(),
-                        decimal: None,
-                    }));
-                }
-            }
-            None => {
-                // Reach the end of input.
-                // No decimal part, the `.` after integer part is another token.
-                return Ok(Some(NumberToken {
-                    integer,
-                    integer_length: raw_integer_chars.len(),
-                    decimal: None,
-                }));
+        let integer_length = s.iter().take_while(|x| x.is_ascii_digit()).count();
+        let decimal_length = match s.get(integer_length) {
+            // May have decimal parts.
+            Some('.') => s[integer_length + 1..]
+                .iter()
+                .take_while(|x| x.is_ascii_digit())
+                .count(),
+            // Only have integer part, or reach the end of input.
+            _ => 0,
+        };
+
+        let mut length = if decimal_length == 0 {
+            // No decimal part, the `.` after integer part is another token.
+            integer_length
+        } else {
+            // Have deciaml part. the `.` after integer part is part of number.
+            integer_length + 1 + decimal_length
+        };
+
+        // Optional exponent, `e` or `E` with an optional sign and at least one digit.
+        // Without digits, the `e` is the start of another token.
+        if matches!(s.get(length), Some('e' | 'E')) {
+            let sign_length = usize::from(matches!(s.get(length + 1), Some('+' | '-')));
+            let exponent_length = s[(length + 1 + sign_length).min(s.len())..]
+                .iter()
+                .take_while(|x| x.is_ascii_digit())
+                .count();
+            if exponent_length > 0 {
+                length += 1 + sign_length + exponent_length;
             }
         }
+
+        Ok(Some(NumberToken {
+            lexeme: s[..length].iter().collect(),
+        }))
     }
 
     fn length(&self) -> usize {
-        match &self.decimal {
-            Some((l, _)) => self.integer_length + 1 + l.len(),
-            None => self.integer_length,
-        }
+        self.lexeme.chars().count()
     }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct IdentifierToken(String);
 
+impl IdentifierToken {
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+
+    /// Check the identifier does not exceed the `limit`.
+    ///
+    /// Extremely long identifiers are rejected to protect later stages, no limit if `limit` is `None`.
+    fn check_length(&self, limit: Option<usize>, line: usize, col: usize) -> Result<(), AppError> {
+        match limit {
+            Some(v) if self.length() > v => Err(AppError::IdentifierTooLong { line, col }),
+            _ => Ok(()),
+        }
+    }
+}
+
 impl Tokened for IdentifierToken {
     fn info(&self) -> (&'static str, String, Option<String>) {
         ("IDENTIFIER", self.0.clone(), None)
     }
 
-    fn from_char_slice(s: &[char], _: usize) -> AppResult<Option<Self>> {
+    /// Identifiers are Unicode letters, digits and `_`, but not starting with a digit.
+    ///
+    /// Letters and digits follow [`char::is_alphanumeric`], so `π` and `naïve` are identifiers.
+    /// Other characters, like emoji or punctuation, end the identifier and are left to other
+    /// tokens or reported as unexpected characters.
+    fn from_char_slice(s: &[char], _: usize, _: usize) -> AppResult<Option<Self>> {
         if s.is_empty() {
             return Ok(None);
         }
 
         let chs = s
             .iter()
-            .take_while(|x| {
-                ((&'a' <= x) && (x <= &&'z'))
-                    || ((&'A' <= x) && (x <= &&'Z'))
-                    || ((&'0' <= x) && (x <= &&'9'))
-                    || x == &&'_'
-            })
+            .take_while(|x| x.is_alphanumeric() || x == &&'_')
             .map(|x| x.to_owned())
             .collect::<Vec<_>>();
 
-        // TODO: Check the first character is number or not before moving forward.
-        if !chs.is_empty() && !chs[0].is_digit(10) {
+        if !chs.is_empty() && !chs[0].is_numeric() {
             Ok(Some(Self(chs.into_iter().collect())))
         } else {
             Ok(None)
@@ -573,10 +795,11 @@ impl Tokened for IdentifierToken {
     }
 
     fn length(&self) -> usize {
-        self.0.len()
+        self.0.chars().count()
     }
 }
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum KeywordToken {
     /// `and`
@@ -626,6 +849,78 @@ pub enum KeywordToken {
 
     /// `While`
     KWhile,
+
+    /// Keyword not in standard lox, added by a [`KeywordSet`].
+    Extension(String),
+}
+
+impl KeywordToken {
+    /// Keywords in standard lox and their spellings.
+    const STANDARD: [(&'static str, KeywordToken); 16] = [
+        ("and", KeywordToken::KAnd),
+        ("class", KeywordToken::KClass),
+        ("else", KeywordToken::KElse),
+        ("false", KeywordToken::KFalse),
+        ("for", KeywordToken::KFor),
+        ("fun", KeywordToken::KFun),
+        ("if", KeywordToken::KIf),
+        ("nil", KeywordToken::KNil),
+        ("or", KeywordToken::KOr),
+        ("print", KeywordToken::KPrint),
+        ("return", KeywordToken::KReturn),
+        ("super", KeywordToken::KSuper),
+        ("this", KeywordToken::KThis),
+        ("true", KeywordToken::KTrue),
+        ("var", KeywordToken::KVar),
+        ("while", KeywordToken::KWhile),
+    ];
+}
+
+/// Words recognized as keywords by the lexer.
+///
+/// Defaults to the standard lox keywords. Removed keywords are lexed as identifiers, and extension
+/// keywords produce [`KeywordToken::Extension`].
+#[derive(Debug, Clone)]
+pub struct KeywordSet(HashMap<String, KeywordToken>);
+
+impl Default for KeywordSet {
+    fn default() -> Self {
+        Self(
+            KeywordToken::STANDARD
+                .iter()
+                .map(|(spelling, keyword)| (spelling.to_string(), keyword.clone()))
+                .collect(),
+        )
+    }
+}
+
+impl KeywordSet {
+    /// Stop treating `spelling` as a keyword.
+    ///
+    /// Return false if `spelling` is not a keyword.
+    pub fn remove(&mut self, spelling: &str) -> bool {
+        self.0.remove(spelling).is_some()
+    }
+
+    /// Treat `spelling` as an extension keyword.
+    pub fn add_extension(&mut self, spelling: String) {
+        self.0
+            .insert(spelling.clone(), KeywordToken::Extension(spelling));
+    }
+
+    /// Find the keyword at the beginning of `s`.
+    fn match_char_slice(
+        &self,
+        s: &[char],
+        line: usize,
+        col: usize,
+    ) -> AppResult<Option<KeywordToken>> {
+        let word = match IdentifierToken::from_char_slice(s, line, col)? {
+            Some(v) => v.0,
+            None => return Ok(None),
+        };
+        Ok(self.0.get(&word).cloned())
+    }
 }
 
 impl Tokened for KeywordToken {
@@ -647,65 +942,22 @@ impl Tokened for KeywordToken {
             KeywordToken::KTrue => ("TRUE", "true".into(), None),
             KeywordToken::KVar => ("VAR", "var".into(), None),
             KeywordToken::KWhile => ("WHILE", "while".into(), None),
+            KeywordToken::Extension(v) => ("KEYWORD", v.clone(), None),
         }
     }
 
-    fn from_char_slice(s: &[char], _: usize) -> AppResult<Option<Self>> {
-        // If better to build a huffman tree, but it is fast enough, I think.
-        let mut it = s.iter();
-        let s0 = it.next();
-        let s1 = it.next();
-        let s2 = it.next();
-        let s3 = it.next();
-        let s4 = it.next();
-        let s5 = it.next();
-
-        let ss = [s0, s1, s2, s3, s4, s5];
-
-        if ss[..3] == [Some(&'a'), Some(&'n'), Some(&'d')] {
-            Ok(Some(Self::KAnd))
-        } else if ss[..5] == [Some(&'c'), Some(&'l'), Some(&'a'), Some(&'s'), Some(&'s')] {
-            Ok(Some(Self::KClass))
-        } else if ss[..4] == [Some(&'e'), Some(&'l'), Some(&'s'), Some(&'e')] {
-            Ok(Some(Self::KElse))
-        } else if ss[..5] == [Some(&'f'), Some(&'a'), Some(&'l'), Some(&'s'), Some(&'e')] {
-            Ok(Some(Self::KFalse))
-        } else if ss[..3] == [Some(&'f'), Some(&'o'), Some(&'r')] {
-            Ok(Some(Self::KFor))
-        } else if ss[..3] == [Some(&'f'), Some(&'u'), Some(&'n')] {
-            Ok(Some(Self::KFun))
-        } else if ss[..2] == [Some(&'i'), Some(&'f')] {
-            Ok(Some(Self::KIf))
-        } else if ss[..3] == [Some(&'n'), Some(&'i'), Some(&'l')] {
-            Ok(Some(Self::KNil))
-        } else if ss[..2] == [Some(&'o'), Some(&'r')] {
-            Ok(Some(Self::KOr))
-        } else if ss[..5] == [Some(&'p'), Some(&'r'), Some(&'i'), Some(&'n'), Some(&'t')] {
-            Ok(Some(Self::KPrint))
-        } else if ss[..6]
-            == [
-                Some(&'r'),
-                Some(&'e'),
-                Some(&'t'),
-                Some(&'u'),
-                Some(&'r'),
-                Some(&'n'),
-            ]
-        {
-            Ok(Some(Self::KReturn))
-        } else if ss[..5] == [Some(&'s'), Some(&'u'), Some(&'p'), Some(&'e'), Some(&'r')] {
-            Ok(Some(Self::KSuper))
-        } else if ss[..4] == [Some(&'t'), Some(&'h'), Some(&'i'), Some(&'s')] {
-            Ok(Some(Self::KThis))
-        } else if ss[..4] == [Some(&'t'), Some(&'r'), Some(&'u'), Some(&'e')] {
-            Ok(Some(Self::KTrue))
-        } else if ss[..3] == [Some(&'v'), Some(&'a'), Some(&'r')] {
-            Ok(Some(Self::KVar))
-        } else if ss[..5] == [Some(&'w'), Some(&'h'), Some(&'i'), Some(&'l'), Some(&'e')] {
-            Ok(Some(Self::KWhile))
-        } else {
-            Ok(None)
-        }
+    /// Match standard lox keywords.
+    ///
+    /// The whole word is compared so identifiers like `android` are not split into keywords.
+    fn from_char_slice(s: &[char], line: usize, col: usize) -> AppResult<Option<Self>> {
+        let word = match IdentifierToken::from_char_slice(s, line, col)? {
+            Some(v) => v.0,
+            None => return Ok(None),
+        };
+        Ok(Self::STANDARD
+            .iter()
+            .find(|(spelling, _)| spelling == &word)
+            .map(|(_, keyword)| keyword.clone()))
     }
 
     fn length(&self) -> usize {
@@ -726,6 +978,7 @@ impl Tokened for KeywordToken {
             KeywordToken::KTrue => 4,
             KeywordToken::KVar => 3,
             KeywordToken::KWhile => 5,
+            KeywordToken::Extension(v) => v.chars().count(),
         }
     }
 }
@@ -0,0 +1,6 @@
+pub mod errors;
+pub mod interpreter;
+pub mod lexer;
+pub mod parser;
+pub mod repl;
+pub mod session;
@@ -1,76 +1,394 @@
+use std::any::Any;
 use std::env;
 use std::fs;
 use std::io::{self, Write};
+use std::panic::{self, AssertUnwindSafe};
 
 use anyhow::Context;
 use anyhow::Ok;
 
-use self::errors::AppResult;
-use self::lexer::Lexer;
-use self::parser::Parser;
-
-mod errors;
-mod lexer;
-mod parser;
+use codecrafters_interpreter::errors::{
+    exit_code_of, AppResult, EXIT_LEX_PARSE_ERROR, EXIT_RUNTIME_ERROR, EXIT_USAGE,
+};
+use codecrafters_interpreter::interpreter::{
+    execute_program, CheckedMathEvaluator, Evaluator, TracingEvaluator, TreeWalkEvaluator,
+};
+use codecrafters_interpreter::lexer::{Lexer, LexerConfig};
+use codecrafters_interpreter::parser::Parser;
+use codecrafters_interpreter::repl;
+use codecrafters_interpreter::session::Session;
 
 fn main() -> AppResult<()> {
     let args: Vec<String> = env::args().collect();
+    let mut session = Session::stdio(!args.iter().any(|x| x.as_str() == "--unbuffered"));
+
+    // Unfinished parts of the interpreter may panic, report them as runtime errors instead of
+    // printing backtraces.
+    panic::set_hook(Box::new(|_| { /* Reported below */ }));
+    panic::catch_unwind(AssertUnwindSafe(|| run(&args, &mut session))).unwrap_or_else(|payload| {
+        let code = report_panic(&mut session.err, payload.as_ref());
+        exit(&mut session, code)
+    })
+}
+
+/// Write the message of a caught panic to `err` as an internal error.
+///
+/// Return the exit code to use.
+fn report_panic(err: &mut dyn Write, payload: &(dyn Any + Send)) -> i32 {
+    let message = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic");
+    let _ = writeln!(err, "Internal error: {message}");
+    EXIT_RUNTIME_ERROR
+}
+
+fn run(args: &[String], session: &mut Session) -> AppResult<()> {
+    match args.get(1).map(String::as_str) {
+        Some("--help" | "-h") => {
+            print_help(session, &args[0])?;
+            return session.flush();
+        }
+        Some("--version") => {
+            writeln!(session.out, "{}", env!("CARGO_PKG_VERSION"))?;
+            return session.flush();
+        }
+        None | Some("repl") => return repl::run(io::stdin().lock(), session),
+        _ => { /* Do nothing */ }
+    }
+
     if args.len() < 3 {
-        writeln!(io::stderr(), "Usage: {} tokenize <filename>", args[0]).unwrap();
-        return Ok(());
+        writeln!(session.err, "Usage: {} tokenize <filename>", args[0])?;
+        exit(session, EXIT_USAGE);
     }
 
     let command = &args[1];
-    let filename = &args[2];
+    let (flags, positional): (Vec<&String>, Vec<&String>) =
+        args[2..].iter().partition(|x| x.starts_with("--"));
+    let filename = match positional.first() {
+        Some(v) => v,
+        None => {
+            writeln!(session.err, "Usage: {} tokenize <filename>", args[0])?;
+            exit(session, EXIT_USAGE);
+        }
+    };
+    if let Some(v) = flags.iter().find(|x| !is_known_flag(x)) {
+        writeln!(session.err, "Unknown flag: {v}")?;
+        writeln!(session.err, "Run '{} --help' for usage.", args[0])?;
+        exit(session, EXIT_USAGE);
+    }
+    let lexer_config = parse_lexer_config(&flags)?;
+    session.with_codes = flags.iter().any(|x| x.as_str() == "--with-codes");
 
     match command.as_str() {
+        "tokenize" if flags.iter().any(|x| x.as_str() == "--diff") => {
+            let other_filename = match positional.get(1) {
+                Some(v) => v,
+                None => {
+                    writeln!(
+                        session.err,
+                        "Usage: {} tokenize --diff <fileA> <fileB>",
+                        args[0]
+                    )?;
+                    exit(session, EXIT_USAGE);
+                }
+            };
+            let mut lexers = vec![];
+            for f in [filename, other_filename] {
+                let input = fs::read_to_string(f).context("failed to read file")?;
+                let mut lexer = Lexer::new(input).with_config(lexer_config.clone());
+                if let Err(e) = lexer.tokenize() {
+                    session.report_any(&e)?;
+                    exit(session, exit_code_of(&e, EXIT_LEX_PARSE_ERROR));
+                }
+                lexer.print_errors(session)?;
+                lexers.push(lexer);
+            }
+            lexers[0].print_diff(&lexers[1], session)?;
+            Ok(())
+        }
+        "tokenize" if flags.iter().any(|x| x.as_str() == "--stats") => {
+            let input = fs::read_to_string(filename).context("failed to read file")?;
+            let mut lexer = Lexer::new(input).with_config(lexer_config);
+            let result = lexer.tokenize();
+            lexer.print_errors(session)?;
+            if let Err(e) = &result {
+                session.report_any(e)?;
+            }
+            let mut stats = lexer.stats();
+            // The error stopping the scan is not recorded in the lexer.
+            stats.errors += usize::from(result.is_err());
+            writeln!(session.out, "{stats}")?;
+            if lexer.has_error() || result.is_err() {
+                exit(session, EXIT_LEX_PARSE_ERROR);
+            }
+            Ok(())
+        }
+        "tokenize" if flags.iter().any(|x| x.as_str() == "--color") => {
+            let input = fs::read_to_string(filename).context("failed to read file")?;
+            let mut lexer = Lexer::new(input).with_config(lexer_config);
+            let color = env::var_os("NO_COLOR").is_none();
+            if let Err(e) = lexer.print_highlighted(session, color) {
+                session.report_any(&e)?;
+                exit(session, exit_code_of(&e, EXIT_LEX_PARSE_ERROR));
+            }
+            lexer.print_errors(session)?;
+            if lexer.has_error() {
+                exit(session, EXIT_LEX_PARSE_ERROR);
+            }
+            Ok(())
+        }
         "tokenize" => {
             let input = fs::read_to_string(filename).context("failed to read file")?;
-            let mut lexer = Lexer::new(input);
+            let mut lexer = Lexer::new(input).with_config(lexer_config);
+            let sexp = flags.iter().any(|x| x.as_str() == "--sexp");
             if let Err(e) = lexer.tokenize() {
-                eprintln!("{}", e);
-                lexer.print_tokens();
-                std::process::exit(65);
+                lexer.print_errors(session)?;
+                session.report_any(&e)?;
+                if sexp {
+                    lexer.print_sexp(session)?;
+                } else {
+                    lexer.print_tokens(session)?;
+                }
+                exit(session, exit_code_of(&e, EXIT_LEX_PARSE_ERROR));
             } else {
-                lexer.print_tokens();
+                lexer.print_errors(session)?;
+                if sexp {
+                    lexer.print_sexp(session)?;
+                } else {
+                    lexer.print_tokens(session)?;
+                }
                 if lexer.has_error() {
-                    std::process::exit(65);
+                    exit(session, EXIT_LEX_PARSE_ERROR);
                 }
             }
-            return Ok(());
+            Ok(())
         }
         "tokenize-text" => {
-            let mut lexer = Lexer::new(filename.to_string());
+            let mut lexer = Lexer::new(filename.to_string()).with_config(lexer_config);
             if let Err(e) = lexer.tokenize() {
-                eprintln!("{}", e);
-                lexer.print_tokens();
-                std::process::exit(65);
+                lexer.print_errors(session)?;
+                session.report_any(&e)?;
+                lexer.print_tokens(session)?;
+                exit(session, exit_code_of(&e, EXIT_LEX_PARSE_ERROR));
             } else {
-                lexer.print_tokens();
+                lexer.print_errors(session)?;
+                lexer.print_tokens(session)?;
                 if lexer.has_error() {
-                    std::process::exit(65);
+                    exit(session, EXIT_LEX_PARSE_ERROR);
                 }
             }
-            return Ok(());
+            Ok(())
         }
         "parse" => {
             let input = fs::read_to_string(filename).context("failed to read file")?;
-            let mut lexer = Lexer::new(input);
+            let mut lexer = Lexer::new(input).with_config(lexer_config);
+            if let Err(e) = lexer.tokenize() {
+                lexer.print_errors(session)?;
+                session.report_any(&e)?;
+                exit(session, exit_code_of(&e, EXIT_LEX_PARSE_ERROR));
+            } else if lexer.has_error() {
+                lexer.print_errors(session)?;
+                exit(session, EXIT_LEX_PARSE_ERROR);
+            }
+
+            // Expression files like `1 + 2` are accepted without the ending `;`.
+            let mut parser =
+                Parser::new(lexer.tokens(), lexer.token_lines()).allow_trailing_without_semicolon();
+            if flags.iter().any(|x| x.as_str() == "--lint") {
+                parser = parser.with_lints();
+            }
+            if let Err(e) = parser.parse() {
+                session.report_any(&e)?;
+                exit(session, exit_code_of(&e, EXIT_LEX_PARSE_ERROR));
+            }
+            for warning in parser.warnings() {
+                writeln!(session.err, "{warning}")?;
+            }
+            if flags.iter().any(|x| x.as_str() == "--fold") {
+                parser.fold_constants();
+            }
+            if flags.iter().any(|x| x.as_str() == "--json") {
+                parser.print_json_info(session)?;
+            } else if flags.iter().any(|x| x.as_str() == "--pretty") {
+                parser.print_pretty_info(session)?;
+            } else {
+                parser.print_info(session)?;
+            }
+            Ok(())
+        }
+        "evaluate" => {
+            if flags.iter().any(|x| x.as_str() == "--trace")
+                && flags.iter().any(|x| x.as_str() == "--checked-math")
+            {
+                writeln!(session.err, "--trace can not be used with --checked-math")?;
+                exit(session, EXIT_USAGE);
+            }
+            let input = fs::read_to_string(filename).context("failed to read file")?;
+            let mut lexer = Lexer::new(input).with_config(lexer_config);
             if let Err(e) = lexer.tokenize() {
-                eprintln!("{}", e);
-                std::process::exit(65);
+                lexer.print_errors(session)?;
+                session.report_any(&e)?;
+                exit(session, exit_code_of(&e, EXIT_LEX_PARSE_ERROR));
             } else if lexer.has_error() {
-                std::process::exit(65);
+                lexer.print_errors(session)?;
+                exit(session, EXIT_LEX_PARSE_ERROR);
             }
 
-            let mut parser = Parser::new(lexer.tokens());
-            parser.parse()?;
-            parser.print_info();
-            return Ok(());
+            // Expression files like `1 + 2` are accepted without the ending `;`.
+            let mut parser =
+                Parser::new(lexer.tokens(), lexer.token_lines()).allow_trailing_without_semicolon();
+            if flags.iter().any(|x| x.as_str() == "--lint") {
+                parser = parser.with_lints();
+            }
+            if let Err(e) = parser.parse() {
+                session.report_any(&e)?;
+                exit(session, exit_code_of(&e, EXIT_LEX_PARSE_ERROR));
+            }
+            for warning in parser.warnings() {
+                writeln!(session.err, "{warning}")?;
+            }
+
+            // Trace goes to `err` while values go to `out`.
+            let mut evaluator: Box<dyn Evaluator + '_> =
+                if flags.iter().any(|x| x.as_str() == "--trace") {
+                    Box::new(TracingEvaluator::new(&mut session.err))
+                } else if flags.iter().any(|x| x.as_str() == "--checked-math") {
+                    Box::new(CheckedMathEvaluator::default())
+                } else {
+                    Box::new(TreeWalkEvaluator::default())
+                };
+            let result = execute_program(evaluator.as_mut(), parser.output(), &mut session.out);
+            drop(evaluator);
+            if let Err(e) = result {
+                session.report_any(&e)?;
+                exit(session, exit_code_of(&e, EXIT_RUNTIME_ERROR));
+            }
+            Ok(())
         }
         _ => {
-            writeln!(io::stderr(), "Unknown command: {}", command).unwrap();
-            return Ok(());
+            writeln!(session.err, "Unknown command: {}", command)?;
+            writeln!(session.err, "Run '{} --help' for usage.", args[0])?;
+            exit(session, EXIT_USAGE);
+        }
+    }?;
+    session.flush()
+}
+
+fn print_help(session: &mut Session, program: &str) -> AppResult<()> {
+    writeln!(session.out, "Usage: {program} <command> [flags] <filename>")?;
+    writeln!(session.out, "       {program} [repl]")?;
+    writeln!(session.out)?;
+    writeln!(session.out, "Commands:")?;
+    writeln!(session.out, "  tokenize       print tokens in file")?;
+    writeln!(
+        session.out,
+        "  tokenize-text  print tokens in the argument text"
+    )?;
+    writeln!(session.out, "  parse          print syntax trees in file")?;
+    writeln!(
+        session.out,
+        "  evaluate       print values of expressions in file"
+    )?;
+    writeln!(
+        session.out,
+        "  repl           evaluate lines from stdin, the default"
+    )?;
+    writeln!(session.out)?;
+    writeln!(session.out, "Options:")?;
+    writeln!(session.out, "  -h, --help     print this help")?;
+    writeln!(session.out, "  --version      print version")?;
+    Ok(())
+}
+
+/// Flags accepted by commands, those ending with `=` take a value.
+const KNOWN_FLAGS: &[&str] = &[
+    "--checked-math",
+    "--color",
+    "--diff",
+    "--disable-keyword=",
+    "--extension-keyword=",
+    "--fail-fast",
+    "--fold",
+    "--json",
+    "--keep-whitespace",
+    "--lint",
+    "--max-identifier-length=",
+    "--normalize-newlines-in-strings",
+    "--pretty",
+    "--sexp",
+    "--stats",
+    "--trace",
+    "--unbuffered",
+    "--with-codes",
+];
+
+fn is_known_flag(flag: &str) -> bool {
+    KNOWN_FLAGS.iter().any(|x| {
+        if x.ends_with('=') {
+            flag.starts_with(x)
+        } else {
+            flag == *x
         }
+    })
+}
+
+/// Flush pending output in `session` and exit the process with `code`.
+fn exit(session: &mut Session, code: i32) -> ! {
+    let _ = session.flush();
+    std::process::exit(code);
+}
+
+/// Build the lexer options from command line flags.
+///
+/// * `--max-identifier-length=N`: reject identifiers longer than `N` characters.
+/// * `--normalize-newlines-in-strings`: convert CRLF and CR in string values to LF.
+/// * `--keep-whitespace`: emit whitespace, line break and comment tokens.
+/// * `--disable-keyword=WORD`: lex keyword `WORD` as an identifier.
+/// * `--extension-keyword=WORD`: lex `WORD` as an extension keyword.
+/// * `--fail-fast`: stop at the first error.
+fn parse_lexer_config(flags: &[&String]) -> AppResult<LexerConfig> {
+    let mut config = LexerConfig::default();
+    for flag in flags {
+        if let Some(v) = flag.strip_prefix("--max-identifier-length=") {
+            let limit = v
+                .parse::<usize>()
+                .with_context(|| format!("invalid identifier length limit: {v}"))?;
+            config.max_identifier_length = Some(limit);
+        } else if flag.as_str() == "--normalize-newlines-in-strings" {
+            config.normalize_newlines_in_strings = true;
+        } else if flag.as_str() == "--keep-whitespace" {
+            config.keep_whitespace = true;
+        } else if let Some(v) = flag.strip_prefix("--disable-keyword=") {
+            config.keywords.remove(v);
+        } else if let Some(v) = flag.strip_prefix("--extension-keyword=") {
+            config.keywords.add_extension(v.to_string());
+        } else if flag.as_str() == "--fail-fast" {
+            config.fail_fast = true;
+        }
+    }
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use codecrafters_interpreter::parser::expr::Value;
+
+    #[test]
+    fn panic_is_internal_error() {
+        // `print` is not a value yet.
+        let mut lexer = Lexer::new("print".to_string());
+        lexer.tokenize().unwrap();
+        let token = lexer.tokens()[0].clone();
+        let payload = panic::catch_unwind(|| Value::try_from(&token)).unwrap_err();
+
+        let mut err = vec![];
+        assert_eq!(report_panic(&mut err, payload.as_ref()), 70);
+        assert_eq!(
+            String::from_utf8(err).unwrap(),
+            "Internal error: not yet implemented\n"
+        );
     }
 }
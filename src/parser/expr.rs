@@ -1,86 +1,338 @@
+use std::io::Write;
+use std::rc::Rc;
+
 use anyhow::{bail, Context};
 
-use crate::errors::AppResult;
-use crate::lexer::{KeywordToken, SingleCharToken, Token};
+use crate::errors::{at_line, AppError, AppResult};
+use crate::interpreter::{Evaluator, TreeWalkEvaluator};
+use crate::lexer::{KeywordToken, MultiCharToken, SingleCharToken, Token};
+use crate::session::Session;
 
+/// Expression node.
+///
+/// Children are shared through [`Rc`] so cloning a node is cheap and does not copy the subtree.
+/// Nodes are immutable once built, transforms like constant folding produce new nodes instead of
+/// modifying shared children in place.
 #[derive(Debug, Clone)]
-pub(super) enum Expr {
+pub enum Expr {
     Binary {
         op: BinaryOp,
-        lhs: Box<Expr>,
-        rhs: Box<Expr>,
+        lhs: Rc<Expr>,
+        rhs: Rc<Expr>,
+
+        /// Line of the operator, where runtime errors are reported.
+        line: usize,
     },
     Value(Value),
     Scope(Scope),
     Unary {
         op: UnaryOp,
-        operand: Box<Expr>,
+        operand: Rc<Expr>,
+
+        /// Line of the operator.
+        line: usize,
+    },
+
+    /// Read the variable of the name.
+    Variable {
+        name: String,
+        line: usize,
+    },
+
+    /// `and` and `or`, separated from [`Expr::Binary`] because `rhs` may not be evaluated.
+    Logical {
+        op: LogicalOp,
+        lhs: Rc<Expr>,
+        rhs: Rc<Expr>,
+    },
+
+    /// `name = value`, evaluates to the assigned value.
+    Assign {
+        name: String,
+        value: Rc<Expr>,
+
+        /// Line of the assigned variable.
+        line: usize,
     },
 }
 
 impl Expr {
-    pub(super) fn new_binary<'a>(
-        op: BinaryOp,
-        lhs: Option<&'a Token>,
-        rhs: Option<&'a Token>,
-    ) -> AppResult<Self> {
-        let lhs = lhs.context("lhs is null")?;
-        let rhs = rhs.context("rhs is null")?;
+    pub fn new_binary(op: BinaryOp, lhs: Expr, rhs: Expr, line: usize) -> Self {
+        Expr::Binary {
+            op,
+            lhs: Rc::new(lhs),
+            rhs: Rc::new(rhs),
+            line,
+        }
+    }
 
-        let expr = Expr::Binary {
+    pub fn new_logical(op: LogicalOp, lhs: Expr, rhs: Expr) -> Self {
+        Expr::Logical {
             op,
-            lhs: Box::new(Expr::new_value(lhs)?),
-            rhs: Box::new(Expr::new_value(rhs)?),
-        };
-        Ok(expr)
+            lhs: Rc::new(lhs),
+            rhs: Rc::new(rhs),
+        }
     }
 
-    pub(super) fn new_value<'a>(v: &'a Token) -> AppResult<Self> {
+    pub fn new_assign(name: String, value: Expr, line: usize) -> Self {
+        Expr::Assign {
+            name,
+            value: Rc::new(value),
+            line,
+        }
+    }
+
+    pub(super) fn new_value(v: &Token) -> AppResult<Self> {
         Ok(Expr::Value(Value::try_from(v)?))
     }
 
-    pub fn new_scope(scope_type: ScopeType, expr: Option<Expr>) -> AppResult<Self> {
-        Ok(Expr::Scope(Scope {
+    pub fn new_scope(scope_type: ScopeType, expr: Option<Expr>) -> Self {
+        Expr::Scope(Scope {
             scope_type,
-            expr: expr.map(Box::new),
-        }))
+            expr: expr.map(Rc::new),
+        })
     }
 
-    pub fn new_unary<'a>(unary_type: UnaryOp, operand: Option<&'a Token>) -> AppResult<Self> {
+    pub fn new_unary(unary_type: UnaryOp, operand: Option<&Token>, line: usize) -> AppResult<Self> {
         let operand = operand.context("operand is null")?;
 
         Ok(Expr::Unary {
             op: unary_type,
-            operand: Box::new(Expr::new_value(operand)?),
+            operand: Rc::new(Expr::new_value(operand)?),
+            line,
         })
     }
 
-    pub fn new_unary_from_expr(unary_type: UnaryOp, expr: Expr) -> Self {
+    pub fn new_unary_from_expr(unary_type: UnaryOp, expr: Expr, line: usize) -> Self {
         Expr::Unary {
             op: unary_type,
-            operand: Box::new(expr),
+            operand: Rc::new(expr),
+            line,
+        }
+    }
+
+    /// Evaluate the expression with standard lox semantics.
+    pub fn evaluate(&self) -> AppResult<Value> {
+        TreeWalkEvaluator::default().evaluate(self)
+    }
+
+    /// Dispatch to the method of `evaluator` for current kind of node.
+    ///
+    /// Runtime errors are reported at the line of the innermost node failed, see [`at_line`].
+    pub fn accept<E: Evaluator + ?Sized>(&self, evaluator: &mut E) -> AppResult<Value> {
+        match self {
+            Expr::Binary { op, lhs, rhs, line } => evaluator
+                .eval_binary(op, lhs, rhs)
+                .map_err(|e| at_line(e, *line)),
+            Expr::Value(v) => evaluator.eval_value(v),
+            Expr::Scope(s) => evaluator.eval_scope(s),
+            Expr::Unary { op, operand, line } => evaluator
+                .eval_unary(op, operand)
+                .map_err(|e| at_line(e, *line)),
+            Expr::Variable { name, line } => {
+                evaluator.eval_variable(name).map_err(|e| at_line(e, *line))
+            }
+            Expr::Logical { op, lhs, rhs } => evaluator.eval_logical(op, lhs, rhs),
+            Expr::Assign { name, value, line } => evaluator
+                .eval_assign(name, value)
+                .map_err(|e| at_line(e, *line)),
+        }
+    }
+
+    pub fn print_info(&self, session: &mut Session) -> AppResult<()> {
+        writeln!(session.out, "{}", self.literal())?;
+        Ok(())
+    }
+
+    pub fn print_pretty_info(&self, session: &mut Session) -> AppResult<()> {
+        writeln!(session.out, "{}", self.pretty(0))?;
+        Ok(())
+    }
+
+    pub fn print_json_info(&self, session: &mut Session) -> AppResult<()> {
+        writeln!(session.out, "{}", self.json())?;
+        Ok(())
+    }
+
+    /// Check the expression only consists of constant values.
+    pub fn is_constant(&self) -> bool {
+        match self {
+            Expr::Binary { lhs, rhs, .. } | Expr::Logical { lhs, rhs, .. } => {
+                lhs.is_constant() && rhs.is_constant()
+            }
+            Expr::Value(..) => true,
+            Expr::Scope(s) => s.expr.as_ref().map_or(true, |x| x.is_constant()),
+            Expr::Unary { operand, .. } => operand.is_constant(),
+            Expr::Variable { .. } | Expr::Assign { .. } => false,
         }
     }
 
-    pub fn print_info(&self) {
-        println!("{}", self.literal());
+    /// Fold constant sub-expressions into values.
+    ///
+    /// Folding follows the evaluating semantics, but operations that would fail (type mismatch or
+    /// divided by zero) are left unfolded so errors still happen at runtime.
+    pub(super) fn fold(self) -> Self {
+        match self {
+            Expr::Binary { op, lhs, rhs, line } => {
+                let lhs = Rc::unwrap_or_clone(lhs).fold();
+                let rhs = Rc::unwrap_or_clone(rhs).fold();
+                let folded = match (&lhs, &rhs) {
+                    (Expr::Value(l), Expr::Value(r)) => op.fold(l, r),
+                    _ => None,
+                };
+                match folded {
+                    Some(v) => Expr::Value(v),
+                    None => Expr::new_binary(op, lhs, rhs, line),
+                }
+            }
+            Expr::Value(..) | Expr::Variable { .. } => self,
+            Expr::Logical { op, lhs, rhs } => {
+                let lhs = Rc::unwrap_or_clone(lhs).fold();
+                let rhs = Rc::unwrap_or_clone(rhs).fold();
+                match &lhs {
+                    Expr::Value(v) if op.short_circuits(v) => lhs,
+                    Expr::Value(..) => rhs,
+                    _ => Expr::new_logical(op, lhs, rhs),
+                }
+            }
+            Expr::Assign { name, value, line } => {
+                Expr::new_assign(name, Rc::unwrap_or_clone(value).fold(), line)
+            }
+            Expr::Scope(s) => match s.expr.map(|x| Rc::unwrap_or_clone(x).fold()) {
+                Some(Expr::Value(v)) => Expr::Value(v),
+                expr => Expr::Scope(Scope {
+                    scope_type: s.scope_type,
+                    expr: expr.map(Rc::new),
+                }),
+            },
+            Expr::Unary { op, operand, line } => {
+                let operand = Rc::unwrap_or_clone(operand).fold();
+                let folded = match &operand {
+                    Expr::Value(v) => op.fold(v),
+                    _ => None,
+                };
+                match folded {
+                    Some(v) => Expr::Value(v),
+                    None => Expr::new_unary_from_expr(op, operand, line),
+                }
+            }
+        }
     }
 
-    fn literal(&self) -> String {
+    /// Render the expression as a multi-line tree.
+    ///
+    /// Each node takes one line, operands are placed on the following lines with one more level of
+    /// `indent`, two spaces per level.
+    pub fn pretty(&self, indent: usize) -> String {
+        let pad = "  ".repeat(indent);
         match self {
-            Expr::Binary { op, lhs, rhs } => {
+            Expr::Binary { op, lhs, rhs, .. } => format!(
+                "{pad}{}\n{}\n{}",
+                op.literal(),
+                lhs.pretty(indent + 1),
+                rhs.pretty(indent + 1)
+            ),
+            Expr::Value(v) => format!("{pad}{}", v.literal()),
+            Expr::Scope(s) => s.pretty(indent),
+            Expr::Unary { op, operand, .. } => {
+                format!("{pad}{}\n{}", op.literal(), operand.pretty(indent + 1))
+            }
+            Expr::Variable { name, .. } => format!("{pad}{name}"),
+            Expr::Logical { op, lhs, rhs } => format!(
+                "{pad}{}\n{}\n{}",
+                op.literal(),
+                lhs.pretty(indent + 1),
+                rhs.pretty(indent + 1)
+            ),
+            Expr::Assign { name, value, .. } => {
+                format!("{pad}= {name}\n{}", value.pretty(indent + 1))
+            }
+        }
+    }
+
+    /// Render the expression as a JSON object, every node has a `"type"` tag.
+    pub(super) fn json(&self) -> String {
+        match self {
+            Expr::Binary { op, lhs, rhs, .. } => format!(
+                r#"{{"type":"binary","op":{},"lhs":{},"rhs":{}}}"#,
+                json_string(op.literal()),
+                lhs.json(),
+                rhs.json()
+            ),
+            Expr::Value(v) => v.json(),
+            Expr::Scope(s) => s.json(),
+            Expr::Unary { op, operand, .. } => format!(
+                r#"{{"type":"unary","op":{},"operand":{}}}"#,
+                json_string(op.literal()),
+                operand.json()
+            ),
+            Expr::Variable { name, .. } => {
+                format!(r#"{{"type":"variable","name":{}}}"#, json_string(name))
+            }
+            Expr::Logical { op, lhs, rhs } => format!(
+                r#"{{"type":"logical","op":{},"lhs":{},"rhs":{}}}"#,
+                json_string(op.literal()),
+                lhs.json(),
+                rhs.json()
+            ),
+            Expr::Assign { name, value, .. } => format!(
+                r#"{{"type":"assign","name":{},"value":{}}}"#,
+                json_string(name),
+                value.json()
+            ),
+        }
+    }
+
+    pub(crate) fn literal(&self) -> String {
+        match self {
+            Expr::Binary { op, lhs, rhs, .. } => {
                 format!("({} {} {})", op.literal(), lhs.literal(), rhs.literal())
             }
             Expr::Value(v) => v.literal(),
             Expr::Scope(s) => s.literal(),
-            Expr::Unary { op, operand } => format!("({} {})", op.literal(), operand.literal()),
+            Expr::Unary { op, operand, .. } => format!("({} {})", op.literal(), operand.literal()),
+            Expr::Variable { name, .. } => name.clone(),
+            Expr::Logical { op, lhs, rhs } => {
+                format!("({} {} {})", op.literal(), lhs.literal(), rhs.literal())
+            }
+            Expr::Assign { name, value, .. } => format!("(= {name} {})", value.literal()),
+        }
+    }
+}
+
+/// Logical operator, only evaluates `rhs` when needed.
+#[derive(Debug, Clone)]
+pub enum LogicalOp {
+    /// `lhs and rhs`
+    And,
+
+    /// `lhs or rhs`
+    Or,
+}
+
+impl LogicalOp {
+    fn literal(&self) -> &'static str {
+        match self {
+            LogicalOp::And => "and",
+            LogicalOp::Or => "or",
+        }
+    }
+
+    /// The result is `lhs` itself without evaluating `rhs`.
+    ///
+    /// `and` stops at a falsey `lhs` and `or` stops at a truthy one. Otherwise the result is `rhs`,
+    /// operands are not converted to booleans.
+    pub(crate) fn short_circuits(&self, lhs: &Value) -> bool {
+        match self {
+            LogicalOp::And => !lhs.is_truthy(),
+            LogicalOp::Or => lhs.is_truthy(),
         }
     }
 }
 
 /// Operator that accepts two operands.
 #[derive(Debug, Clone)]
-pub(super) enum BinaryOp {
+pub enum BinaryOp {
     /// `lhs + rhs`
     Plus,
 
@@ -92,6 +344,24 @@ pub(super) enum BinaryOp {
 
     /// `lhs / rhs`
     Divide,
+
+    /// `lhs < rhs`
+    Less,
+
+    /// `lhs > rhs`
+    Greater,
+
+    /// `lhs <= rhs`
+    LessEqual,
+
+    /// `lhs >= rhs`
+    GreaterEqual,
+
+    /// `lhs == rhs`
+    EqualEqual,
+
+    /// `lhs != rhs`
+    BangEqual,
 }
 
 impl BinaryOp {
@@ -101,12 +371,118 @@ impl BinaryOp {
             BinaryOp::Minus => "-",
             BinaryOp::Multiply => "*",
             BinaryOp::Divide => "/",
+            BinaryOp::Less => "<",
+            BinaryOp::Greater => ">",
+            BinaryOp::LessEqual => "<=",
+            BinaryOp::GreaterEqual => ">=",
+            BinaryOp::EqualEqual => "==",
+            BinaryOp::BangEqual => "!=",
+        }
+    }
+
+    /// Apply the operator on values.
+    ///
+    /// `+` accepts two numbers or two strings, `==` and `!=` accept any values, other operators
+    /// only accept numbers. Dividing by zero is an error.
+    pub(crate) fn apply(&self, lhs: &Value, rhs: &Value) -> AppResult<Value> {
+        match self {
+            BinaryOp::Plus => check_plus_operands(lhs, rhs),
+            BinaryOp::Minus => {
+                let (l, r) = check_number_operands(lhs, rhs)?;
+                Ok(Value::new_number_as(l - r, both_integers(lhs, rhs)))
+            }
+            BinaryOp::Multiply => check_multiply_operands(lhs, rhs),
+            BinaryOp::Divide => {
+                let (l, r) = check_number_operands(lhs, rhs)?;
+                if r == 0.0 {
+                    return Err(AppError::DivisionByZero.into());
+                }
+                Ok(Value::new_number(l / r))
+            }
+            BinaryOp::Less => {
+                let (l, r) = check_number_operands(lhs, rhs)?;
+                Ok(Value::Bool(l < r))
+            }
+            BinaryOp::Greater => {
+                let (l, r) = check_number_operands(lhs, rhs)?;
+                Ok(Value::Bool(l > r))
+            }
+            BinaryOp::LessEqual => {
+                let (l, r) = check_number_operands(lhs, rhs)?;
+                Ok(Value::Bool(l <= r))
+            }
+            BinaryOp::GreaterEqual => {
+                let (l, r) = check_number_operands(lhs, rhs)?;
+                Ok(Value::Bool(l >= r))
+            }
+            BinaryOp::EqualEqual => Ok(Value::Bool(lhs.equals(rhs))),
+            BinaryOp::BangEqual => Ok(Value::Bool(!lhs.equals(rhs))),
+        }
+    }
+
+    /// Apply the operator on constant values.
+    ///
+    /// Return `None` if the operation fails on given values, like divided by zero.
+    fn fold(&self, lhs: &Value, rhs: &Value) -> Option<Value> {
+        self.apply(lhs, rhs).ok()
+    }
+}
+
+/// Check both operands are numbers, return their values.
+fn check_number_operands(lhs: &Value, rhs: &Value) -> AppResult<(f64, f64)> {
+    match (lhs, rhs) {
+        (Value::Number { value: l, .. }, Value::Number { value: r, .. }) => Ok((*l, *r)),
+        _ => Err(AppError::OperandsNotNumbers.into()),
+    }
+}
+
+/// Both operands are integer numbers.
+fn both_integers(lhs: &Value, rhs: &Value) -> bool {
+    lhs.is_integer() && rhs.is_integer()
+}
+
+/// Add two numbers, or concatenate two strings.
+fn check_plus_operands(lhs: &Value, rhs: &Value) -> AppResult<Value> {
+    match (lhs, rhs) {
+        (Value::Number { value: l, .. }, Value::Number { value: r, .. }) => {
+            Ok(Value::new_number_as(l + r, both_integers(lhs, rhs)))
+        }
+        (Value::String { value: l, .. }, Value::String { value: r, .. }) => {
+            Ok(Value::new_string(format!("{l}{r}")))
+        }
+        _ => Err(AppError::OperandsNotNumbersOrStrings.into()),
+    }
+}
+
+/// Max length in bytes of a string produced by repetition.
+const MAX_REPEAT_LENGTH: usize = 1 << 24;
+
+/// Multiply two numbers, or repeat a string by a number of times.
+///
+/// Repeating is rejected before allocating if the result would exceed [`MAX_REPEAT_LENGTH`].
+fn check_multiply_operands(lhs: &Value, rhs: &Value) -> AppResult<Value> {
+    match (lhs, rhs) {
+        (Value::String { value: s, .. }, Value::Number { value: n, .. })
+        | (Value::Number { value: n, .. }, Value::String { value: s, .. }) => {
+            if *n < 0.0 || n.fract() != 0.0 {
+                return Err(AppError::InvalidRepeatCount.into());
+            }
+            // Saturates for counts not fitting in `usize`.
+            let count = *n as usize;
+            match s.len().checked_mul(count) {
+                Some(v) if v <= MAX_REPEAT_LENGTH => Ok(Value::new_string(s.repeat(count))),
+                _ => Err(AppError::RepeatTooLong.into()),
+            }
+        }
+        _ => {
+            let (l, r) = check_number_operands(lhs, rhs)?;
+            Ok(Value::new_number_as(l * r, both_integers(lhs, rhs)))
         }
     }
 }
 
 impl<'a> TryFrom<&'a Token> for BinaryOp {
-    type Error = anyhow::Error;
+    type Error = AppError;
 
     fn try_from(value: &'a Token) -> Result<Self, Self::Error> {
         match value {
@@ -124,26 +500,37 @@ impl<'a> TryFrom<&'a Token> for BinaryOp {
                 SingleCharToken::Plus => Ok(BinaryOp::Plus),
                 SingleCharToken::Minus => Ok(BinaryOp::Minus),
                 SingleCharToken::Slash => Ok(BinaryOp::Divide),
-                SingleCharToken::Less => todo!(),
-                SingleCharToken::Greater => todo!(),
+                SingleCharToken::Less => Ok(BinaryOp::Less),
+                SingleCharToken::Greater => Ok(BinaryOp::Greater),
             },
             Token::String(..)
             | Token::Number(..)
             | Token::Identifier(..)
             | Token::Keyword(..)
-            | Token::Ignored(..) => unreachable!("check before convert"),
-            Token::MultiCharToken(..) => todo!(),
+            | Token::Ignored(..)
+            | Token::Eof => unreachable!("check before convert"),
+            Token::MultiCharToken(m) => match m {
+                MultiCharToken::EqualEqual => Ok(BinaryOp::EqualEqual),
+                MultiCharToken::BangEqual => Ok(BinaryOp::BangEqual),
+                MultiCharToken::LessEqual => Ok(BinaryOp::LessEqual),
+                MultiCharToken::GreaterEqual => Ok(BinaryOp::GreaterEqual),
+            },
         }
     }
 }
 
 /// The value evaluating on.
 #[derive(Debug, Clone)]
-pub(super) enum Value {
+pub enum Value {
     /// Number.
     ///
-    /// Int or float.
-    Number { value: f64, info: String },
+    /// Int or float, `integer` is true if written without decimal part or computed from integers
+    /// only.
+    Number {
+        value: f64,
+        info: String,
+        integer: bool,
+    },
 
     /// String value.
     String { value: String, info: String },
@@ -156,9 +543,90 @@ pub(super) enum Value {
 }
 
 impl Value {
-    fn literal(&self) -> String {
+    fn new_number(value: f64) -> Self {
+        Self::new_number_as(value, false)
+    }
+
+    pub(crate) fn new_number_as(value: f64, integer: bool) -> Self {
+        let info = if value.fract() == 0.0 {
+            format!("{value:.1}")
+        } else {
+            value.to_string()
+        };
+        Value::Number {
+            value,
+            info,
+            integer,
+        }
+    }
+
+    fn new_string(value: String) -> Self {
+        Value::String {
+            info: value.clone(),
+            value,
+        }
+    }
+
+    /// Lox truthiness: `nil` and `false` are falsey, everything else is truthy.
+    pub fn to_bool(&self) -> bool {
+        !matches!(self, Value::Bool(false) | Value::Nil)
+    }
+
+    /// Lox equality: values of different kinds are never equal, `nil` equals `nil`.
+    pub fn equals(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Number { value: l, .. }, Value::Number { value: r, .. }) => l == r,
+            (Value::String { value: l, .. }, Value::String { value: r, .. }) => l == r,
+            (Value::Bool(l), Value::Bool(r)) => l == r,
+            (Value::Nil, Value::Nil) => true,
+            _ => false,
+        }
+    }
+
+    /// Is a number without decimal part.
+    pub fn is_integer(&self) -> bool {
+        matches!(self, Value::Number { integer: true, .. })
+    }
+
+    /// The number held by a [`Value::Number`], `None` for other values.
+    pub fn to_number(&self) -> Option<f64> {
+        match self {
+            Value::Number { value, .. } => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn is_truthy(&self) -> bool {
+        self.to_bool()
+    }
+
+    /// Name of the kind of value, for introspection.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number { .. } => "number",
+            Value::String { .. } => "string",
+            Value::Bool(..) => "boolean",
+            Value::Nil => "nil",
+        }
+    }
+
+    fn json(&self) -> String {
+        match self {
+            Value::Number { value, .. } if value.is_finite() => {
+                format!(r#"{{"type":"number","value":{value}}}"#)
+            }
+            Value::Number { .. } => r#"{"type":"number","value":null}"#.into(),
+            Value::String { value, .. } => {
+                format!(r#"{{"type":"string","value":{}}}"#, json_string(value))
+            }
+            Value::Bool(v) => format!(r#"{{"type":"boolean","value":{v}}}"#),
+            Value::Nil => r#"{"type":"nil"}"#.into(),
+        }
+    }
+
+    pub(crate) fn literal(&self) -> String {
         match self {
-            Value::Number { info, .. } => format!("{info}"),
+            Value::Number { info, .. } => info.to_string(),
             Value::String { info, .. } => info.clone(),
             Value::Bool(v) => v.to_string(),
             Value::Nil => "nil".into(),
@@ -172,12 +640,13 @@ impl<'a> TryFrom<&'a Token> for Value {
     fn try_from(value: &'a Token) -> Result<Self, Self::Error> {
         match value {
             Token::String(s) => Ok(Value::String {
-                value: s.0.to_owned(),
-                info: s.0.to_owned(),
+                value: s.value.to_owned(),
+                info: s.value.to_owned(),
             }),
             Token::Number(n) => Ok(Value::Number {
                 value: n.as_f64(),
                 info: n.info_string(),
+                integer: n.is_integer(),
             }),
             Token::Keyword(k) => match k {
                 KeywordToken::KAnd => todo!(),
@@ -196,6 +665,7 @@ impl<'a> TryFrom<&'a Token> for Value {
                 KeywordToken::KTrue => Ok(Value::Bool(true)),
                 KeywordToken::KVar => todo!(),
                 KeywordToken::KWhile => todo!(),
+                KeywordToken::Extension(..) => bail!("invalid value {value:?}"),
             },
             v => bail!("invalid value {v:?}"),
         }
@@ -204,25 +674,64 @@ impl<'a> TryFrom<&'a Token> for Value {
 
 #[derive(Debug, Clone)]
 pub enum ScopeType {
+    /// `( )`
     Paren,
+
+    /// `[ ]`
+    Bracket,
+
+    /// `{ }`
+    Brace,
+}
+
+impl ScopeType {
+    const fn literal(&self) -> &'static str {
+        match self {
+            ScopeType::Paren => "group",
+            ScopeType::Bracket => "list",
+            ScopeType::Brace => "block",
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Scope {
     scope_type: ScopeType,
-    expr: Option<Box<Expr>>,
+    expr: Option<Rc<Expr>>,
 }
 
 impl Scope {
+    pub fn scope_type(&self) -> &ScopeType {
+        &self.scope_type
+    }
+
+    /// The expression inside the scope, `None` if the scope is empty.
+    pub fn expr(&self) -> Option<&Expr> {
+        self.expr.as_deref()
+    }
+
     pub fn literal(&self) -> String {
-        match self.scope_type {
-            ScopeType::Paren => format!(
-                "(group {})",
-                self.expr
-                    .as_ref()
-                    .map(|x| x.as_ref().literal())
-                    .unwrap_or_else(String::new)
-            ),
+        match &self.expr {
+            Some(v) => format!("({} {})", self.scope_type.literal(), v.literal()),
+            // No trailing space in an empty scope.
+            None => format!("({})", self.scope_type.literal()),
+        }
+    }
+
+    fn json(&self) -> String {
+        let name = self.scope_type.literal();
+        let expr = self
+            .expr
+            .as_ref()
+            .map_or("null".into(), |x| x.as_ref().json());
+        format!(r#"{{"type":"{name}","expr":{expr}}}"#)
+    }
+
+    fn pretty(&self, indent: usize) -> String {
+        let name = self.scope_type.literal();
+        match &self.expr {
+            Some(v) => format!("{}{name}\n{}", "  ".repeat(indent), v.pretty(indent + 1)),
+            None => format!("{}{name}", "  ".repeat(indent)),
         }
     }
 }
@@ -243,10 +752,30 @@ impl UnaryOp {
             UnaryOp::LogicalNot => "!",
         }
     }
+
+    /// Apply the operator on value.
+    ///
+    /// `-` only accepts number, `!` accepts any value.
+    pub(crate) fn apply(&self, operand: &Value) -> AppResult<Value> {
+        match (self, operand) {
+            (UnaryOp::Negation, Value::Number { value, integer, .. }) => {
+                Ok(Value::new_number_as(-value, *integer))
+            }
+            (UnaryOp::Negation, _) => Err(AppError::OperandNotNumber.into()),
+            (UnaryOp::LogicalNot, v) => Ok(Value::Bool(!v.is_truthy())),
+        }
+    }
+
+    /// Apply the operator on a constant value.
+    ///
+    /// Return `None` if the operation is invalid on given value.
+    fn fold(&self, operand: &Value) -> Option<Value> {
+        self.apply(operand).ok()
+    }
 }
 
 impl<'a> TryFrom<&'a Token> for UnaryOp {
-    type Error = anyhow::Error;
+    type Error = AppError;
 
     fn try_from(value: &'a Token) -> Result<Self, Self::Error> {
         match value {
@@ -272,7 +801,111 @@ impl<'a> TryFrom<&'a Token> for UnaryOp {
             | Token::String(..)
             | Token::Number(..)
             | Token::Identifier(..)
-            | Token::Keyword(..) => unreachable!("check before convert"),
+            | Token::Keyword(..)
+            | Token::Eof => unreachable!("check before convert"),
         }
     }
 }
+
+/// Quote `s` as a JSON string.
+pub(super) fn json_string(s: &str) -> String {
+    let mut out = String::from('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repeat(lhs: Value, rhs: Value) -> AppResult<Value> {
+        BinaryOp::Multiply.apply(&lhs, &rhs)
+    }
+
+    fn error_of(result: AppResult<Value>) -> AppError {
+        result.unwrap_err().downcast::<AppError>().unwrap()
+    }
+
+    #[test]
+    fn string_repetition() {
+        let v = repeat(Value::new_string("ab".into()), Value::new_number(3.0)).unwrap();
+        assert_eq!(v.literal(), "ababab");
+        let v = repeat(Value::new_number(3.0), Value::new_string("x".into())).unwrap();
+        assert_eq!(v.literal(), "xxx");
+    }
+
+    #[test]
+    fn string_repetition_invalid_count() {
+        let e = error_of(repeat(
+            Value::new_string("ab".into()),
+            Value::new_number(-1.0),
+        ));
+        assert!(matches!(e, AppError::InvalidRepeatCount));
+        let e = error_of(repeat(
+            Value::new_string("ab".into()),
+            Value::new_number(1.5),
+        ));
+        assert!(matches!(e, AppError::InvalidRepeatCount));
+    }
+
+    #[test]
+    fn string_repetition_huge_count() {
+        for n in [1e12, 1e300] {
+            let e = error_of(repeat(Value::new_string("ab".into()), Value::new_number(n)));
+            assert!(matches!(e, AppError::RepeatTooLong));
+        }
+    }
+
+    #[test]
+    fn type_name() {
+        assert_eq!(Value::new_number(1.5).type_name(), "number");
+        assert_eq!(Value::new_string("a".into()).type_name(), "string");
+        assert_eq!(Value::Bool(false).type_name(), "boolean");
+        assert_eq!(Value::Nil.type_name(), "nil");
+    }
+
+    #[test]
+    fn scope_literal() {
+        let one = || Some(Expr::Value(Value::new_number(1.0)));
+        assert_eq!(
+            Expr::new_scope(ScopeType::Paren, one()).literal(),
+            "(group 1.0)"
+        );
+        assert_eq!(
+            Expr::new_scope(ScopeType::Bracket, one()).literal(),
+            "(list 1.0)"
+        );
+        assert_eq!(
+            Expr::new_scope(ScopeType::Brace, one()).literal(),
+            "(block 1.0)"
+        );
+        assert_eq!(Expr::new_scope(ScopeType::Brace, None).literal(), "(block)");
+    }
+
+    #[test]
+    fn to_bool_and_to_number() {
+        let values = [
+            Value::new_number(0.0),
+            Value::new_number(2.5),
+            Value::new_string("".into()),
+            Value::Bool(true),
+            Value::Bool(false),
+            Value::Nil,
+        ];
+        let bools: Vec<_> = values.iter().map(Value::to_bool).collect();
+        assert_eq!(bools, [true, true, true, true, false, false]);
+        let numbers: Vec<_> = values.iter().map(Value::to_number).collect();
+        assert_eq!(numbers, [Some(0.0), Some(2.5), None, None, None, None]);
+    }
+}
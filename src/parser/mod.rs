@@ -1,240 +1,966 @@
-use crate::errors::AppResult;
+use std::collections::HashSet;
+
+use crate::errors::{AppError, AppResult};
 use crate::lexer::{KeywordToken, SingleCharToken, Token};
+use crate::session::Session;
 
-use anyhow::{bail, Ok};
+use anyhow::Context;
 use expr::Expr;
 
-use self::expr::{BinaryOp, ScopeType, UnaryOp};
+use self::expr::{BinaryOp, LogicalOp, ScopeType, UnaryOp, Value};
+use self::stmt::Stmt;
+
+pub mod expr;
+pub mod stmt;
 
-mod expr;
+/// Errors found when parsing are always [`AppError`]s.
+type ParseResult<T> = Result<T, AppError>;
 
+/// Recursive descent parser producing statements.
+///
+/// Grammar, expressions are from the lowest precedence:
+///
+/// ```text
+/// program     -> declaration* EOF
+/// declaration -> varDecl | statement
+/// varDecl     -> "var" IDENTIFIER ( "=" expression )? ";"
+/// statement   -> forStmt | ifStmt | whileStmt | block | exprStmt
+/// forStmt     -> "for" "(" ( varDecl | exprStmt | ";" ) expression? ";" expression? ")" statement
+/// ifStmt      -> "if" "(" expression ")" statement ( "else" statement )?
+/// whileStmt   -> "while" "(" expression ")" statement
+/// block       -> "{" declaration* "}"
+/// exprStmt    -> expression ";"
+///
+/// expression -> assignment
+/// assignment -> IDENTIFIER "=" assignment | logicOr
+/// logicOr    -> logicAnd ( "or" logicAnd )*
+/// logicAnd   -> equality ( "and" equality )*
+/// equality   -> comparison ( ( "!=" | "==" ) comparison )*
+/// comparison -> term ( ( ">" | ">=" | "<" | "<=" ) term )*
+/// term       -> factor ( ( "-" | "+" ) factor )*
+/// factor     -> unary ( ( "/" | "*" ) unary )*
+/// unary      -> ( "!" | "-" ) unary | primary
+/// primary    -> NUMBER | STRING | "true" | "false" | "nil" | "(" expression ")" | IDENTIFIER
+/// ```
+///
+/// The `;` ending the last statement is optional in
+/// [`Parser::allow_trailing_without_semicolon`] mode.
 pub struct Parser<'a> {
     /// Tokens to parse.
-    input: &'a Vec<Token>,
+    input: &'a [Token],
 
-    /// Count of tokens in `input`.
-    length: usize,
+    /// Line where each token in `input` starts.
+    lines: &'a [usize],
 
     /// Current parsing postion.
     pos: usize,
 
-    /// The start position of current parsing statement.
-    begin: usize,
-
     /// The output.
-    output: Vec<Expr>,
+    output: Vec<Stmt>,
+
+    /// Accept the last statement without `;`.
+    allow_trailing_without_semicolon: bool,
 
-    /// Current parsed tokens that waiting to form an expr.
-    curr: Vec<&'a Token>,
+    /// Warn about likely mistakes that are still valid code, see [`Parser::with_lints`].
+    lints: bool,
+
+    /// Warnings found when parsing.
+    warnings: Vec<String>,
+
+    /// Names declared in each enclosing local scope, innermost last.
+    ///
+    /// Empty at the top level, where declaring a name again is allowed.
+    scopes: Vec<HashSet<String>>,
 }
 
 impl<'a> Parser<'a> {
-    pub fn new(input: &'a Vec<Token>) -> Self {
-        let length = input.len();
+    /// Parse `input` tokens, errors are reported at the line in `lines` of the same index.
+    ///
+    /// Use [`Lexer::tokens`] and [`Lexer::token_lines`] of the same lexer.
+    ///
+    /// [`Lexer::tokens`]: crate::lexer::Lexer::tokens
+    /// [`Lexer::token_lines`]: crate::lexer::Lexer::token_lines
+    pub fn new(input: &'a [Token], lines: &'a [usize]) -> Self {
         Self {
             input,
-            length,
+            lines,
             pos: 0,
-            begin: 0,
             output: vec![],
-            curr: vec![],
+            allow_trailing_without_semicolon: false,
+            lints: false,
+            warnings: vec![],
+            scopes: vec![],
         }
     }
 
+    /// Accept the last statement in input without the ending `;`, like `1 + 2` in the REPL.
+    ///
+    /// Statements are always required to end with `;` by default.
+    pub fn allow_trailing_without_semicolon(mut self) -> Self {
+        self.allow_trailing_without_semicolon = true;
+        self
+    }
+
+    /// Collect warnings about valid code that is likely a mistake into [`Parser::warnings`].
+    ///
+    /// * An assignment used directly as an `if` or `while` condition, probably meant `==`.
+    ///
+    /// Parsing is not changed by lints.
+    pub fn with_lints(mut self) -> Self {
+        self.lints = true;
+        self
+    }
+
+    #[must_use = "parse errors are only reported in the returned result"]
     pub fn parse(&mut self) -> AppResult<()> {
-        let mut with_tokens: Option<Vec<&'a Token>> = None;
         while !self.finished() {
-            let (t, step) = self.collapse(&self.input[self.begin..], with_tokens)?;
-            with_tokens = t;
-            if step > 0 {
-                self.begin += step;
-            } else if step == 0 {
-                bail!("?????")
+            let line = self.line();
+            let stmt = self
+                .declaration()
+                .with_context(|| format!("while parsing statement starting at line {line}"))?;
+            self.output.push(stmt);
+        }
+
+        Ok(())
+    }
+
+    /// Parse all `tokens` at once.
+    ///
+    /// After an error, skip to the next statement and go on parsing. Return all statements parsed
+    /// successfully together with all errors.
+    pub fn parse_program(tokens: &[Token], lines: &[usize]) -> (Vec<Stmt>, Vec<AppError>) {
+        let mut parser = Parser::new(tokens, lines);
+        let mut errors = vec![];
+        while !parser.finished() {
+            match parser.declaration() {
+                Ok(v) => parser.output.push(v),
+                Err(e) => {
+                    errors.push(e);
+                    parser.synchronize();
+                }
             }
-            self.advance_pos(step);
         }
-        for t in self.curr.iter() {
-            self.output.push(Expr::new_value(t)?);
+        (parser.output, errors)
+    }
+
+    /// Warnings found when parsing with [`Parser::with_lints`].
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Parsed statements.
+    pub fn output(&self) -> &Vec<Stmt> {
+        &self.output
+    }
+
+    /// Fold constant expressions in output into values.
+    pub fn fold_constants(&mut self) {
+        self.output = std::mem::take(&mut self.output)
+            .into_iter()
+            .map(Stmt::fold)
+            .collect();
+    }
+
+    pub fn print_info(&self, session: &mut Session) -> AppResult<()> {
+        for expr in self.output.iter() {
+            expr.print_info(session)?;
         }
+        Ok(())
+    }
 
+    /// Print output as indented trees.
+    pub fn print_pretty_info(&self, session: &mut Session) -> AppResult<()> {
+        for expr in self.output.iter() {
+            expr.print_pretty_info(session)?;
+        }
         Ok(())
     }
 
-    pub fn print_info(&self) {
+    /// Print output as JSON objects, one expression per line.
+    pub fn print_json_info(&self, session: &mut Session) -> AppResult<()> {
         for expr in self.output.iter() {
-            expr.print_info();
+            expr.print_json_info(session)?;
         }
+        Ok(())
     }
 
-    fn finished(&self) -> bool {
-        self.begin > self.length - 1 && self.pos > self.length - 1
+    /// Parsing ends at the end of input or the [`Token::Eof`].
+    fn finished(&mut self) -> bool {
+        self.peek().map_or(true, |x| x == &Token::Eof)
     }
 
-    fn advance_pos(&mut self, step: usize) {
-        self.pos += step;
+    /// The current token, whitespace and comments are skipped.
+    fn peek(&mut self) -> Option<&'a Token> {
+        while let Some(Token::Ignored(..)) = self.input.get(self.pos) {
+            self.pos += 1;
+        }
+        self.input.get(self.pos)
     }
 
-    fn collapse(
-        &mut self,
-        tokens: &'a [Token],
-        with_tokens: Option<Vec<&'a Token>>,
-    ) -> AppResult<(Option<Vec<&'a Token>>, usize)> {
-        let mut parse_buf = with_tokens.unwrap_or_default();
-        let (expr, step) = Self::parse_expr(tokens, &mut parse_buf)?;
-        match expr {
-            Some(v) => {
-                self.output.push(v);
-                Ok((None, step))
+    fn advance(&mut self) {
+        self.pos += 1;
+    }
+
+    /// Line of the current token, or the last line at the end of input.
+    fn line(&mut self) -> usize {
+        self.peek();
+        self.lines
+            .get(self.pos)
+            .or(self.lines.last())
+            .copied()
+            .unwrap_or(1)
+    }
+
+    /// The current token is `expected`.
+    fn check(&mut self, expected: &SingleCharToken) -> bool {
+        matches!(self.peek(), Some(Token::SingleCharacter(v)) if v == expected)
+    }
+
+    /// Consume the current token if it is `expected`.
+    fn matches(&mut self, expected: SingleCharToken) -> bool {
+        if self.check(&expected) {
+            self.advance();
+            return true;
+        }
+        false
+    }
+
+    /// Consume the current token if it is the `expected` keyword.
+    fn matches_keyword(&mut self, expected: KeywordToken) -> bool {
+        if self.peek() == Some(&Token::Keyword(expected)) {
+            self.advance();
+            return true;
+        }
+        false
+    }
+
+    /// Consume the current token, fail if it is not `expected` which should come `after` something.
+    fn expect(&mut self, expected: SingleCharToken, after: &'static str) -> ParseResult<()> {
+        if !self.matches(expected.clone()) {
+            return Err(AppError::ExpectedToken {
+                line: self.line(),
+                at: Self::error_at(self.peek()),
+                expected: Token::SingleCharacter(expected).info().1,
+                after,
+            });
+        }
+        Ok(())
+    }
+
+    /// Skip tokens after an error until the next statement, which starts after a `;` or at a
+    /// statement keyword.
+    ///
+    /// At least one token is skipped so that parsing always moves on.
+    fn synchronize(&mut self) {
+        while let Some(token) = self.peek().filter(|x| x != &&Token::Eof) {
+            self.advance();
+            if token == &Token::SingleCharacter(SingleCharToken::Semicolon) {
+                return;
             }
-            None => {
-                if parse_buf.is_empty() {
-                    bail!("no expr or token produced when parsing expr")
-                } else {
-                    Ok((Some(parse_buf), step))
-                }
+            if matches!(
+                self.peek(),
+                Some(Token::Keyword(
+                    KeywordToken::KClass
+                        | KeywordToken::KFun
+                        | KeywordToken::KVar
+                        | KeywordToken::KFor
+                        | KeywordToken::KIf
+                        | KeywordToken::KWhile
+                        | KeywordToken::KPrint
+                        | KeywordToken::KReturn
+                ))
+            ) {
+                return;
             }
         }
     }
 
-    fn parse_expr(
-        tokens: &'a [Token],
-        parsed_tokens: &mut Vec<&'a Token>,
-    ) -> AppResult<(Option<Expr>, usize)> {
-        match &tokens[0] {
-            Token::SingleCharacter(t) => match t {
-                SingleCharToken::LeftParen => {
-                    let mut parsed_tokens = vec![];
-                    let (parsed_expr, step) = Self::parse_expr(&tokens[1..], &mut parsed_tokens)?;
-                    if !parsed_tokens.is_empty() {
-                        bail!("token left in paren")
-                    }
-                    match tokens.get(1 + step) {
-                        Some(v) if v == &Token::SingleCharacter(SingleCharToken::RightParen) => {
-                            return Ok((
-                                Some(Expr::new_scope(ScopeType::Paren, parsed_expr)?),
-                                step + 1 + 1,
-                            ));
-                        }
-                        _ => bail!("paren not ended"),
-                    }
-                }
-                SingleCharToken::RightParen => {
-                    return Ok((None, 1));
-                }
-                SingleCharToken::LeftBrace => todo!(),
-                SingleCharToken::RightBrace => todo!(),
-                SingleCharToken::Star => todo!(),
-                SingleCharToken::Dot => todo!(),
-                SingleCharToken::Comma => todo!(),
-                SingleCharToken::Plus => {
-                    parsed_tokens.push(&tokens[0]);
-                    return Ok((None, 1));
-                }
-                SingleCharToken::Minus => {
-                    parsed_tokens.push(&tokens[0]);
-                    return Ok((None, 1));
-                }
-                SingleCharToken::Slash => todo!(),
-                SingleCharToken::Semicolon => todo!(),
-                SingleCharToken::Assign => todo!(),
-                SingleCharToken::Bang => {
-                    let bang = UnaryOp::LogicalNot;
-                    let mut parsed_tokens = vec![];
-                    let (expr, step) = Self::parse_expr(&tokens[1..], &mut parsed_tokens)?;
-                    if !parsed_tokens.is_empty() {
-                        bail!("token left after '!' unary op: {:?}", parsed_tokens);
-                    }
-                    match expr {
-                        Some(v) => Ok((
-                            Some(Expr::Unary {
-                                op: bang,
-                                operand: Box::new(v),
-                            }),
-                            1 + step,
-                        )),
-                        None => bail!("empty expr after bang"),
-                    }
-                }
-                SingleCharToken::Less => todo!(),
-                SingleCharToken::Greater => todo!(),
-            },
-            Token::MultiCharToken(..) => {
-                parsed_tokens.push(&tokens[0]);
-                return Ok((None, 1));
+    /// Consume the `;` ending a statement which comes `after` something.
+    ///
+    /// The `;` may be omitted at the end of input if allowed. An operand right after the
+    /// expression, like `2` in `1 2`, is reported as a missing operator.
+    fn expect_semicolon(&mut self, after: &'static str) -> ParseResult<()> {
+        if self.allow_trailing_without_semicolon && self.finished() {
+            return Ok(());
+        }
+        let operand = self
+            .peek()
+            .filter(|x| matches!(x, Token::Identifier(..)) || x.literal_value().is_some());
+        if let Some(v) = operand {
+            return Err(AppError::ExpectOperator {
+                line: self.line(),
+                at: Self::error_at(Some(v)),
+            });
+        }
+        self.expect(SingleCharToken::Semicolon, after)
+    }
+
+    fn declaration(&mut self) -> ParseResult<Stmt> {
+        match self.peek() {
+            Some(Token::Keyword(KeywordToken::KVar)) => {
+                self.advance();
+                self.var_declaration()
             }
-            Token::Ignored(..) => {
-                /* Do nothing */
-                return Ok((None, 1));
+            _ => self.statement(),
+        }
+    }
+
+    fn statement(&mut self) -> ParseResult<Stmt> {
+        if self.matches_keyword(KeywordToken::KFor) {
+            return self.for_statement();
+        }
+        if self.matches_keyword(KeywordToken::KIf) {
+            return self.if_statement();
+        }
+        if self.matches_keyword(KeywordToken::KWhile) {
+            return self.while_statement();
+        }
+        if self.matches(SingleCharToken::LeftBrace) {
+            return self.block();
+        }
+        self.expression_statement()
+    }
+
+    /// The `if` is already consumed.
+    ///
+    /// An `else` binds to the nearest `if`, `if (a) if (b) x else y` runs `y` when `a` is truthy
+    /// but `b` is not.
+    fn if_statement(&mut self) -> ParseResult<Stmt> {
+        self.expect(SingleCharToken::LeftParen, "'if'")?;
+        let condition = self.expression()?;
+        self.lint_condition(&condition);
+        self.expect(SingleCharToken::RightParen, "if condition")?;
+        let then_branch = Box::new(self.statement()?);
+        let else_branch = if self.matches_keyword(KeywordToken::KElse) {
+            Some(Box::new(self.statement()?))
+        } else {
+            None
+        };
+        Ok(Stmt::If {
+            condition,
+            then_branch,
+            else_branch,
+        })
+    }
+
+    /// The `while` is already consumed.
+    fn while_statement(&mut self) -> ParseResult<Stmt> {
+        self.expect(SingleCharToken::LeftParen, "'while'")?;
+        let condition = self.expression()?;
+        self.lint_condition(&condition);
+        self.expect(SingleCharToken::RightParen, "condition")?;
+        let body = Box::new(self.statement()?);
+        Ok(Stmt::While { condition, body })
+    }
+
+    /// The `for` is already consumed.
+    ///
+    /// There is no dedicated node for `for` loops, `for (init; cond; incr) body` is desugared into
+    /// `{ init; while (cond) { body; incr; } }`. A missing condition is `true`.
+    fn for_statement(&mut self) -> ParseResult<Stmt> {
+        // The loop variable is local to the loop.
+        self.in_scope(Self::for_clauses_and_body)
+    }
+
+    fn for_clauses_and_body(&mut self) -> ParseResult<Stmt> {
+        self.expect(SingleCharToken::LeftParen, "'for'")?;
+        let initializer = if self.matches(SingleCharToken::Semicolon) {
+            None
+        } else if self.matches_keyword(KeywordToken::KVar) {
+            Some(self.var_declaration()?)
+        } else {
+            let expr = self.expression()?;
+            self.expect(SingleCharToken::Semicolon, "loop initializer")?;
+            Some(Stmt::Expression(expr))
+        };
+        let condition = if self.check(&SingleCharToken::Semicolon) {
+            Expr::Value(Value::Bool(true))
+        } else {
+            self.expression()?
+        };
+        self.expect(SingleCharToken::Semicolon, "loop condition")?;
+        let increment = if self.check(&SingleCharToken::RightParen) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.expect(SingleCharToken::RightParen, "for clauses")?;
+
+        let mut body = self.statement()?;
+        if let Some(v) = increment {
+            body = Stmt::Block(vec![body, Stmt::Expression(v)]);
+        }
+        let body = Stmt::While {
+            condition,
+            body: Box::new(body),
+        };
+        Ok(match initializer {
+            Some(v) => Stmt::Block(vec![v, body]),
+            None => body,
+        })
+    }
+
+    /// Statements until the `}`, the `{` is already consumed.
+    fn block(&mut self) -> ParseResult<Stmt> {
+        self.in_scope(|parser| {
+            let mut stmts = vec![];
+            while !parser.finished() && !parser.check(&SingleCharToken::RightBrace) {
+                stmts.push(parser.declaration()?);
             }
-            Token::String(..) | Token::Number(..) => {
-                match parsed_tokens.last() {
-                    Some(v) => {
-                        let lhs = parsed_tokens.iter().rev().nth(1);
-                        // Binary operator needs an valid lhs.
-                        if v.is_binary_op() && lhs.is_some_and(|t| t.is_string_or_number()) {
-                            let expr = Expr::new_binary(
-                                BinaryOp::try_from(*v).unwrap(),
-                                lhs.map(|x| *x),
-                                Some(&tokens[0]),
-                            )?;
-                            parsed_tokens.pop();
-                            parsed_tokens.pop();
-                            return Ok((Some(expr), 1));
-                        } else if v.is_unary_op() {
-                            let expr =
-                                Expr::new_unary(UnaryOp::try_from(*v).unwrap(), Some(&tokens[0]))?;
-                            parsed_tokens.pop();
-                            return Ok((Some(expr), 1));
-                        } else {
-                            bail!("adjacent token before string/number is not BinaryOp")
-                        }
-                    }
-                    None => {
-                        /* Do nothing */
-                        return Ok((Some(Expr::new_value(&tokens[0])?), 1));
-                    }
-                }
+            parser.expect(SingleCharToken::RightBrace, "block")?;
+            Ok(Stmt::Block(stmts))
+        })
+    }
+
+    /// Warn about an assignment used as a condition, like `if (x = 1)`.
+    fn lint_condition(&mut self, condition: &Expr) {
+        if let (true, Expr::Assign { line, .. }) = (self.lints, condition) {
+            self.warnings.push(format!(
+                "[line {line}] Warning: Assignment in condition; did you mean '=='?"
+            ));
+        }
+    }
+
+    /// Run `f` in a new local scope, the scope is left even if `f` failed.
+    fn in_scope<T>(&mut self, f: impl FnOnce(&mut Self) -> ParseResult<T>) -> ParseResult<T> {
+        self.scopes.push(HashSet::new());
+        let result = f(self);
+        self.scopes.pop();
+        result
+    }
+
+    fn var_declaration(&mut self) -> ParseResult<Stmt> {
+        let name = match self.peek() {
+            Some(Token::Identifier(v)) => v.name().to_string(),
+            v => {
+                return Err(AppError::ExpectVariableName {
+                    line: self.line(),
+                    at: Self::error_at(v),
+                })
+            }
+        };
+        // Only local scopes forbid declaring a name twice.
+        if let Some(scope) = self.scopes.last_mut() {
+            if !scope.insert(name.clone()) {
+                return Err(AppError::AlreadyDeclared {
+                    line: self.line(),
+                    at: Self::error_at(self.peek()),
+                    name,
+                });
             }
-            Token::Identifier(..) => todo!(),
-            Token::Keyword(v) => match v {
-                KeywordToken::KAnd => todo!(),
-                KeywordToken::KClass => todo!(),
-                KeywordToken::KElse => todo!(),
-                KeywordToken::KFalse | KeywordToken::KTrue => {
-                    let mut ops = vec![];
-                    while parsed_tokens.last().is_some_and(|x| x.is_unary_op()) {
-                        let op = parsed_tokens.pop().unwrap();
-                        let op = UnaryOp::try_from(op).unwrap();
-                        ops.push(op);
-                    }
-                    if ops.is_empty() {
-                        return Ok((Some(Expr::new_value(&tokens[0])?), 1));
-                    } else {
-                        let mut expr = None;
-                        for op in ops.into_iter().rev() {
-                            expr = match expr {
-                                Some(v) => Some(Expr::new_unary_from_expr(op, v)),
-                                None => Some(Expr::new_unary(op, Some(&tokens[0]))?),
-                            };
-                        }
-
-                        return Ok((expr, 1));
-                    }
-                }
-                KeywordToken::KFor => todo!(),
-                KeywordToken::KFun => todo!(),
-                KeywordToken::KIf => todo!(),
-                KeywordToken::KNil => {
-                    return Ok((Some(Expr::new_value(&tokens[0])?), 1));
-                }
-                KeywordToken::KOr => todo!(),
-                KeywordToken::KPrint => todo!(),
-                KeywordToken::KReturn => todo!(),
-                KeywordToken::KSuper => todo!(),
-                KeywordToken::KThis => todo!(),
-                KeywordToken::KVar => todo!(),
-                KeywordToken::KWhile => todo!(),
-            },
         }
+        self.advance();
+        let initializer = if self.matches(SingleCharToken::Assign) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+        self.expect_semicolon("variable declaration")?;
+        Ok(Stmt::Var { name, initializer })
+    }
+
+    fn expression_statement(&mut self) -> ParseResult<Stmt> {
+        let expr = self.expression()?;
+        self.expect_semicolon("expression")?;
+        Ok(Stmt::Expression(expr))
+    }
+
+    fn expression(&mut self) -> ParseResult<Expr> {
+        self.assignment()
+    }
+
+    /// Assignment is right associative, `a = b = 1` assigns `1` to `b` and then `a`.
+    ///
+    /// The target is parsed as an expression first and only variables are valid targets.
+    fn assignment(&mut self) -> ParseResult<Expr> {
+        let expr = self.logic_or()?;
+        let line = self.line();
+        if !self.matches(SingleCharToken::Assign) {
+            return Ok(expr);
+        }
+        let value = self.assignment()?;
+        match expr {
+            Expr::Variable { name, line } => Ok(Expr::new_assign(name, value, line)),
+            _ => Err(AppError::InvalidAssignmentTarget { line }),
+        }
+    }
+
+    fn logic_or(&mut self) -> ParseResult<Expr> {
+        self.logical(KeywordToken::KOr, LogicalOp::Or, Self::logic_and)
+    }
+
+    fn logic_and(&mut self) -> ParseResult<Expr> {
+        self.logical(KeywordToken::KAnd, LogicalOp::And, Self::equality)
+    }
+
+    /// Parse left associative logical operator `op` spelled as `keyword`.
+    fn logical(
+        &mut self,
+        keyword: KeywordToken,
+        op: LogicalOp,
+        operand: fn(&mut Self) -> ParseResult<Expr>,
+    ) -> ParseResult<Expr> {
+        let mut expr = operand(self)?;
+        while self.matches_keyword(keyword.clone()) {
+            let rhs = operand(self)?;
+            expr = Expr::new_logical(op.clone(), expr, rhs);
+        }
+        Ok(expr)
+    }
+
+    fn equality(&mut self) -> ParseResult<Expr> {
+        self.binary(2, Self::comparison)
+    }
+
+    fn comparison(&mut self) -> ParseResult<Expr> {
+        self.binary(3, Self::term)
+    }
+
+    fn term(&mut self) -> ParseResult<Expr> {
+        self.binary(4, Self::factor)
+    }
+
+    fn factor(&mut self) -> ParseResult<Expr> {
+        self.binary(5, Self::unary)
+    }
+
+    /// Parse left associative binary operators of `precedence` level, see [`Token::precedence`].
+    ///
+    /// Operands are parsed by `operand`, the next level binding tighter.
+    fn binary(
+        &mut self,
+        precedence: u8,
+        operand: fn(&mut Self) -> ParseResult<Expr>,
+    ) -> ParseResult<Expr> {
+        let mut expr = operand(self)?;
+        while let Some(token) = self.peek().filter(|x| x.precedence() == Some(precedence)) {
+            let line = self.line();
+            self.advance();
+            let op = BinaryOp::try_from(token)?;
+            let rhs = operand(self)?;
+            expr = Expr::new_binary(op, expr, rhs, line);
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> ParseResult<Expr> {
+        match self.peek() {
+            Some(token) if token.is_unary_op() => {
+                let line = self.line();
+                self.advance();
+                let op = UnaryOp::try_from(token)?;
+                let operand = self.unary()?;
+                Ok(Expr::new_unary_from_expr(op, operand, line))
+            }
+            _ => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> ParseResult<Expr> {
+        let token = self.peek();
+        if let Some(v) = token.and_then(Token::literal_value) {
+            self.advance();
+            return Ok(Expr::Value(v));
+        }
+
+        match token {
+            Some(Token::SingleCharacter(SingleCharToken::LeftParen)) => {
+                self.advance();
+                let expr = self.expression()?;
+                self.expect(SingleCharToken::RightParen, "expression")?;
+                Ok(Expr::new_scope(ScopeType::Paren, Some(expr)))
+            }
+            Some(Token::Identifier(v)) => {
+                let line = self.line();
+                self.advance();
+                Ok(Expr::Variable {
+                    name: v.name().to_string(),
+                    line,
+                })
+            }
+            v => Err(AppError::ExpectExpression {
+                line: self.line(),
+                at: Self::error_at(v),
+            }),
+        }
+    }
+
+    /// Describe where the error is, at `token` or at the end of input.
+    fn error_at(token: Option<&Token>) -> String {
+        match token {
+            None | Some(Token::Eof) => "at end".into(),
+            Some(v) => format!("at '{}'", v.info().1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn parse_in_mode(input: &str, allow_trailing: bool) -> AppResult<Vec<Stmt>> {
+        let mut lexer = Lexer::new(input.to_string());
+        lexer.tokenize()?;
+        let mut parser = Parser::new(lexer.tokens(), lexer.token_lines());
+        if allow_trailing {
+            parser = parser.allow_trailing_without_semicolon();
+        }
+        parser.parse()?;
+        Ok(parser.output)
+    }
+
+    fn parse(input: &str) -> AppResult<Vec<Stmt>> {
+        parse_in_mode(input, false)
+    }
+
+    fn literals(input: &str) -> Vec<String> {
+        parse(input).unwrap().iter().map(Stmt::literal).collect()
+    }
+
+    /// The expression of the only statement in `input`.
+    fn expression(input: &str) -> Expr {
+        match parse(input).unwrap().remove(0) {
+            Stmt::Expression(v) => v,
+            v => panic!("expected an expression statement, got {v:?}"),
+        }
+    }
+
+    fn parse_error(input: &str) -> AppError {
+        parse(input)
+            .unwrap_err()
+            .downcast::<AppError>()
+            .expect("parse error should be an AppError")
+    }
+
+    #[test]
+    fn binary_precedence() {
+        assert_eq!(literals("1 + 2 * 3;"), ["(+ 1.0 (* 2.0 3.0))"]);
+        assert_eq!(literals("(1 + 2) * 3;"), ["(* (group (+ 1.0 2.0)) 3.0)"]);
+    }
+
+    #[test]
+    fn multiplication_associates_left() {
+        assert_eq!(literals("4 * 5;"), ["(* 4.0 5.0)"]);
+        assert_eq!(literals("2 * 3 * 4;"), ["(* (* 2.0 3.0) 4.0)"]);
+        assert_eq!(literals("8 / 4 * 2;"), ["(* (/ 8.0 4.0) 2.0)"]);
+    }
+
+    #[test]
+    fn parse_error_codes() {
+        let err = parse_error("+;");
+        assert_eq!(err.code(), "E0015");
+        assert_eq!(err.to_string(), "[line 1] Error at '+': Expect expression.");
+
+        let err = parse_error("var 1;");
+        assert_eq!(err.code(), "E0016");
+        assert_eq!(
+            err.to_string(),
+            "[line 1] Error at '1': Expect variable name."
+        );
+
+        let err = parse_error("1 = 2;");
+        assert_eq!(err.code(), "E0017");
+        assert_eq!(
+            err.to_string(),
+            "[line 1] Error at '=': Invalid assignment target."
+        );
+        assert_eq!(err.exit_code(), 65);
+    }
+
+    #[test]
+    fn strict_mode_requires_semicolon() {
+        assert_eq!(
+            parse_error("1 + 2").to_string(),
+            "[line 1] Error at end: Expect ';' after expression."
+        );
+        assert_eq!(
+            parse_error("var a = 1").to_string(),
+            "[line 1] Error at end: Expect ';' after variable declaration."
+        );
+        assert_eq!(literals("1 + 2;"), ["(+ 1.0 2.0)"]);
+    }
+
+    #[test]
+    fn statements_do_not_merge_across_lines() {
+        assert_eq!(
+            parse_error("2\n-\"a\"").to_string(),
+            "[line 2] Error at end: Expect ';' after expression."
+        );
+        assert_eq!(literals("2;\n-\"a\";"), ["2.0", "(- a)"]);
+    }
+
+    #[test]
+    fn missing_operator() {
+        for (input, at) in [
+            ("1 2;", "2"),
+            ("\"a\" \"b\";", "\"b\""),
+            ("true false;", "false"),
+        ] {
+            let err = parse_error(input);
+            assert_eq!(err.code(), "E0018");
+            assert_eq!(
+                err.to_string(),
+                format!("[line 1] Error at '{at}': Expect operator between operands.")
+            );
+        }
+        assert_eq!(
+            parse_error("var a = 1 +\n2\nb;").to_string(),
+            "[line 3] Error at 'b': Expect operator between operands."
+        );
+        assert_eq!(
+            parse_in_mode("1 2", true)
+                .unwrap_err()
+                .downcast::<AppError>()
+                .unwrap()
+                .to_string(),
+            "[line 1] Error at '2': Expect operator between operands."
+        );
+    }
+
+    #[test]
+    fn parse_program_recovers() {
+        let parse_program = |input: &str| {
+            let mut lexer = Lexer::new(input.to_string());
+            lexer.tokenize().unwrap();
+            Parser::parse_program(lexer.tokens(), lexer.token_lines())
+        };
+
+        let (stmts, errors) = parse_program("1 + 2;\n(3 4;");
+        assert_eq!(stmts.len(), 1);
+        assert_eq!(stmts[0].literal(), "(+ 1.0 2.0)");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 2] Error at '4': Expect ')' after expression."
+        );
+
+        let (stmts, errors) = parse_program("+;\nvar 1;\nclass\nvar a = 2;\na;");
+        assert_eq!(stmts.len(), 2);
+        let lines = errors.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                "[line 1] Error at '+': Expect expression.",
+                "[line 2] Error at '1': Expect variable name.",
+                "[line 3] Error at 'class': Expect expression.",
+            ]
+        );
+    }
+
+    fn folded(input: &str) -> Vec<String> {
+        let mut lexer = Lexer::new(input.to_string());
+        lexer.tokenize().unwrap();
+        let mut parser = Parser::new(lexer.tokens(), lexer.token_lines());
+        parser.parse().unwrap();
+        parser.fold_constants();
+        parser.output.iter().map(Stmt::literal).collect()
+    }
+
+    #[test]
+    fn fold_constants() {
+        assert_eq!(folded("1 + 2 * 3;"), ["7.0"]);
+        assert_eq!(folded("!true;"), ["false"]);
+        assert_eq!(folded("\"a\" + \"b\";"), ["ab"]);
+        assert_eq!(folded("var y = (2 + 3) * x;"), folded("var y = 5 * x;"));
+    }
+
+    #[test]
+    fn fold_constant_children() {
+        assert_eq!(folded("x + 2 * 3;"), ["(+ x 6.0)"]);
+        assert_eq!(folded("if (x) 1 + 1;"), folded("if (x) 2;"));
+        assert_eq!(
+            folded("while (x == 1 + 1) x = x - (1 + 2);"),
+            folded("while (x == 2) x = x - 3;")
+        );
+    }
+
+    #[test]
+    fn fold_keeps_failing_operations() {
+        assert_eq!(folded("1 / 0;"), ["(/ 1.0 0.0)"]);
+        assert_eq!(folded("1 + \"a\";"), ["(+ 1.0 a)"]);
+        assert_eq!(folded("-\"a\" + (1 + 1);"), ["(+ (- a) 2.0)"]);
+    }
+
+    #[test]
+    fn is_constant() {
+        let expr = |input: &str| match parse(input).unwrap().remove(0) {
+            Stmt::Expression(e) => e,
+            v => panic!("not an expression statement: {v:?}"),
+        };
+        assert!(expr("(1 + 2) * -3 == nil;").is_constant());
+        assert!(!expr("1 + x;").is_constant());
+        assert!(!expr("x = 1;").is_constant());
+    }
+
+    #[test]
+    fn lenient_mode_allows_trailing_statement() {
+        let stmts = parse_in_mode("var a = 1; a + 2", true).unwrap();
+        assert_eq!(stmts.len(), 2);
+        assert_eq!(stmts[1].literal(), "(+ a 2.0)");
+        assert!(parse_in_mode("var a = 1", true).is_ok());
+        // Only the last statement may omit `;`.
+        assert!(parse_in_mode("1 + 2\n3;", true).is_err());
+        assert!(parse_in_mode("{ 1 }", true).is_err());
+    }
+
+    #[test]
+    fn parse_error_context() {
+        let err = parse("1;\nvar a =\n  (2;").unwrap_err();
+        let chain = err.chain().map(|x| x.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            chain,
+            [
+                "while parsing statement starting at line 2",
+                "[line 3] Error at ';': Expect ')' after expression.",
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_error_line() {
+        assert_eq!(
+            parse_error("1;\n\"a\nb\";\n(2;").to_string(),
+            "[line 4] Error at ';': Expect ')' after expression."
+        );
+        assert_eq!(
+            parse_error("1;\n2 +\n").to_string(),
+            "[line 3] Error at end: Expect expression."
+        );
+    }
+
+    #[test]
+    fn comparison_operators() {
+        assert_eq!(literals("3 < 5;"), ["(< 3.0 5.0)"]);
+        assert_eq!(literals("3 <= 5;"), ["(<= 3.0 5.0)"]);
+        assert_eq!(literals("3 > 5;"), ["(> 3.0 5.0)"]);
+        assert_eq!(literals("3 >= 5;"), ["(>= 3.0 5.0)"]);
+        assert_eq!(literals("3 == 5;"), ["(== 3.0 5.0)"]);
+        assert_eq!(literals("3 != 5;"), ["(!= 3.0 5.0)"]);
+        // Comparison binds tighter than equality.
+        assert_eq!(literals("1 < 2 == true;"), ["(== (< 1.0 2.0) true)"]);
+    }
+
+    #[test]
+    fn pretty_print() {
+        let expr = expression("1 + 2 * 3;");
+        assert_eq!(expr.pretty(0), "+\n  1.0\n  *\n    2.0\n    3.0");
+        assert_eq!(expr.pretty(1), "  +\n    1.0\n    *\n      2.0\n      3.0");
+    }
+
+    #[test]
+    fn json_output() {
+        assert_eq!(
+            expression("1 + 2;").json(),
+            concat!(
+                r#"{"type":"binary","op":"+","#,
+                r#""lhs":{"type":"number","value":1},"#,
+                r#""rhs":{"type":"number","value":2}}"#
+            )
+        );
+    }
+
+    #[test]
+    fn printer_spacing() {
+        assert_eq!(literals("-(1 + 2);"), ["(- (group (+ 1.0 2.0)))"]);
+        assert_eq!(literals("((1));"), ["(group (group 1.0))"]);
+        assert_eq!(literals("!-\"a b\";"), ["(! (- a b))"]);
+        assert_eq!(literals("(1) * -2;"), ["(* (group 1.0) (- 2.0))"]);
+    }
+
+    #[test]
+    fn clone_shares_children() {
+        let expr = expression("(1 + 2) * -(3 - 4 / 5);");
+        let cloned = expr.clone();
+        match (&expr, &cloned) {
+            (
+                Expr::Binary { lhs, rhs, .. },
+                Expr::Binary {
+                    lhs: cloned_lhs,
+                    rhs: cloned_rhs,
+                    ..
+                },
+            ) => {
+                assert!(Rc::ptr_eq(lhs, cloned_lhs));
+                assert!(Rc::ptr_eq(rhs, cloned_rhs));
+            }
+            v => panic!("expected binary expressions, got {v:?}"),
+        }
+        assert_eq!(cloned.literal(), expr.literal());
+    }
+
+    #[test]
+    fn empty_input() {
+        let mut parser = Parser::new(&[], &[]);
+        parser.parse().unwrap();
+        assert!(parser.output().is_empty());
+        let (stmts, errors) = Parser::parse_program(&[], &[]);
+        assert!(stmts.is_empty() && errors.is_empty());
+
+        // An empty file only has the EOF token.
+        assert!(parse("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn assignment_binds_looser_than_or() {
+        assert_eq!(literals("a = b or c;"), ["(= a (or b c))"]);
+        assert_eq!(literals("a = b = c and d;"), ["(= a (= b (and c d)))"]);
+        let err = parse_error("a or b = c;");
+        assert_eq!(err.code(), "E0017");
+        assert_eq!(
+            err.to_string(),
+            "[line 1] Error at '=': Invalid assignment target."
+        );
+    }
+
+    #[test]
+    fn local_redeclaration() {
+        let err = parse_error("{\n  var a = 1;\n  var a = 2;\n}");
+        assert_eq!(err.code(), "E0019");
+        assert_eq!(
+            err.to_string(),
+            "[line 3] Error at 'a': Already a variable named 'a' in this scope."
+        );
+        assert_eq!(
+            parse_error("for (var i = 0; i < 1;) { var j; var j; }").code(),
+            "E0019"
+        );
+
+        // Globals may be declared again, and locals may shadow outer names.
+        assert!(parse("var a = 1; var a = 2;").is_ok());
+        assert!(parse("var a; { var a; { var a; } } { var a; }").is_ok());
+        assert!(parse("for (var i = 0; i < 1;) { var i; }").is_ok());
+    }
+
+    #[test]
+    fn lint_assignment_in_condition() {
+        let warnings = |input: &str| {
+            let mut lexer = Lexer::new(input.to_string());
+            lexer.tokenize().unwrap();
+            let mut parser = Parser::new(lexer.tokens(), lexer.token_lines()).with_lints();
+            parser.parse().unwrap();
+            (
+                parser.warnings().to_vec(),
+                parser.output.iter().map(Stmt::literal).collect::<Vec<_>>(),
+            )
+        };
+        let (found, stmts) = warnings("var x;\nif (x = 1) x;");
+        assert_eq!(
+            found,
+            ["[line 2] Warning: Assignment in condition; did you mean '=='?"]
+        );
+        assert_eq!(stmts, literals("var x;\nif (x = 1) x;"));
+        assert_eq!(warnings("var x; while (x = nil) x;").0.len(), 1);
+        assert!(warnings("var x; if (x == 1) x;").0.is_empty());
+        // A grouped assignment is explicit.
+        assert!(warnings("var x; if ((x = 1)) x;").0.is_empty());
+
+        let mut lexer = Lexer::new("var x; if (x = 1) x;".to_string());
+        lexer.tokenize().unwrap();
+        let mut parser = Parser::new(lexer.tokens(), lexer.token_lines());
+        parser.parse().unwrap();
+        assert!(parser.warnings().is_empty());
     }
 }
@@ -0,0 +1,180 @@
+use std::io::Write;
+
+use crate::errors::AppResult;
+use crate::session::Session;
+
+use super::expr::{json_string, Expr};
+
+/// Statement node, the top level unit of a program.
+#[derive(Debug, Clone)]
+pub enum Stmt {
+    /// An expression evaluated for its value.
+    Expression(Expr),
+
+    /// `var name = initializer;`, the variable is `nil` if there is no initializer.
+    Var {
+        name: String,
+        initializer: Option<Expr>,
+    },
+
+    /// `{ ... }`, statements executed in a nested scope.
+    Block(Vec<Stmt>),
+
+    /// `if (condition) then_branch else else_branch`, the `else` part is optional.
+    If {
+        condition: Expr,
+        then_branch: Box<Stmt>,
+        else_branch: Option<Box<Stmt>>,
+    },
+
+    /// `while (condition) body`.
+    ///
+    /// `for` loops are desugared into it.
+    While { condition: Expr, body: Box<Stmt> },
+}
+
+impl Stmt {
+    pub fn print_info(&self, session: &mut Session) -> AppResult<()> {
+        writeln!(session.out, "{}", self.literal())?;
+        Ok(())
+    }
+
+    pub fn print_pretty_info(&self, session: &mut Session) -> AppResult<()> {
+        writeln!(session.out, "{}", self.pretty(0))?;
+        Ok(())
+    }
+
+    pub fn print_json_info(&self, session: &mut Session) -> AppResult<()> {
+        writeln!(session.out, "{}", self.json())?;
+        Ok(())
+    }
+
+    /// Fold constant expressions into values, see [`Expr::fold`].
+    pub(super) fn fold(self) -> Self {
+        match self {
+            Stmt::Expression(e) => Stmt::Expression(e.fold()),
+            Stmt::Var { name, initializer } => Stmt::Var {
+                name,
+                initializer: initializer.map(Expr::fold),
+            },
+            Stmt::Block(stmts) => Stmt::Block(stmts.into_iter().map(Stmt::fold).collect()),
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => Stmt::If {
+                condition: condition.fold(),
+                then_branch: Box::new(then_branch.fold()),
+                else_branch: else_branch.map(|x| Box::new(x.fold())),
+            },
+            Stmt::While { condition, body } => Stmt::While {
+                condition: condition.fold(),
+                body: Box::new(body.fold()),
+            },
+        }
+    }
+
+    /// Render the statement as a multi-line tree, see [`Expr::pretty`].
+    fn pretty(&self, indent: usize) -> String {
+        match self {
+            Stmt::Expression(e) => e.pretty(indent),
+            Stmt::Var { name, initializer } => {
+                let pad = "  ".repeat(indent);
+                match initializer {
+                    Some(v) => format!("{pad}var {name}\n{}", v.pretty(indent + 1)),
+                    None => format!("{pad}var {name}"),
+                }
+            }
+            Stmt::Block(stmts) => std::iter::once(format!("{}block", "  ".repeat(indent)))
+                .chain(stmts.iter().map(|x| x.pretty(indent + 1)))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                let mut lines = vec![
+                    format!("{}if", "  ".repeat(indent)),
+                    condition.pretty(indent + 1),
+                    then_branch.pretty(indent + 1),
+                ];
+                if let Some(v) = else_branch {
+                    lines.push(v.pretty(indent + 1));
+                }
+                lines.join("\n")
+            }
+            Stmt::While { condition, body } => format!(
+                "{}while\n{}\n{}",
+                "  ".repeat(indent),
+                condition.pretty(indent + 1),
+                body.pretty(indent + 1)
+            ),
+        }
+    }
+
+    /// Render the statement as a JSON object, expression statements are the expression itself.
+    fn json(&self) -> String {
+        match self {
+            Stmt::Expression(e) => e.json(),
+            Stmt::Var { name, initializer } => format!(
+                r#"{{"type":"var","name":{},"initializer":{}}}"#,
+                json_string(name),
+                initializer.as_ref().map_or("null".into(), Expr::json)
+            ),
+            Stmt::Block(stmts) => format!(
+                r#"{{"type":"block","statements":[{}]}}"#,
+                stmts.iter().map(Stmt::json).collect::<Vec<_>>().join(",")
+            ),
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => format!(
+                r#"{{"type":"if","condition":{},"then":{},"else":{}}}"#,
+                condition.json(),
+                then_branch.json(),
+                else_branch.as_ref().map_or("null".into(), |x| x.json())
+            ),
+            Stmt::While { condition, body } => format!(
+                r#"{{"type":"while","condition":{},"body":{}}}"#,
+                condition.json(),
+                body.json()
+            ),
+        }
+    }
+
+    pub(crate) fn literal(&self) -> String {
+        match self {
+            Stmt::Expression(e) => e.literal(),
+            Stmt::Var { name, initializer } => match initializer {
+                Some(v) => format!("(var {name} {})", v.literal()),
+                None => format!("(var {name})"),
+            },
+            Stmt::Block(stmts) => {
+                // No trailing space in an empty block.
+                let inner = stmts
+                    .iter()
+                    .map(|x| format!(" {}", x.literal()))
+                    .collect::<String>();
+                format!("(block{inner})")
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => match else_branch {
+                Some(v) => format!(
+                    "(if {} {} {})",
+                    condition.literal(),
+                    then_branch.literal(),
+                    v.literal()
+                ),
+                None => format!("(if {} {})", condition.literal(), then_branch.literal()),
+            },
+            Stmt::While { condition, body } => {
+                format!("(while {} {})", condition.literal(), body.literal())
+            }
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use std::io::{BufRead, Write};
+
+use anyhow::Context;
+
+use crate::errors::AppResult;
+use crate::interpreter::{execute_program, TreeWalkEvaluator};
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::session::Session;
+
+/// Read lines from `input` and execute each one as a program, until the end of input.
+///
+/// Values of expression statements are printed. Errors are reported and the next line is read,
+/// variables declared in previous lines are kept.
+pub fn run<R: BufRead>(input: R, session: &mut Session) -> AppResult<()> {
+    let mut evaluator = TreeWalkEvaluator::default();
+    let mut lines = input.lines();
+    loop {
+        write!(session.out, "> ")?;
+        session.flush()?;
+        let line = match lines.next() {
+            Some(v) => v.context("failed to read input")?,
+            None => break,
+        };
+
+        let mut lexer = Lexer::new(line);
+        if let Err(e) = lexer.tokenize() {
+            lexer.print_errors(session)?;
+            session.report_any(&e)?;
+            continue;
+        } else if lexer.has_error() {
+            lexer.print_errors(session)?;
+            continue;
+        }
+
+        let mut parser =
+            Parser::new(lexer.tokens(), lexer.token_lines()).allow_trailing_without_semicolon();
+        if let Err(e) = parser.parse() {
+            session.report_any(&e)?;
+            continue;
+        }
+        if let Err(e) = execute_program(&mut evaluator, parser.output(), &mut session.out) {
+            session.report_any(&e)?;
+        }
+    }
+    writeln!(session.out)?;
+    session.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::SharedBuffer;
+
+    #[test]
+    fn repl_keeps_variables_and_goes_on_after_errors() {
+        let out = SharedBuffer::default();
+        let err = SharedBuffer::default();
+        let mut session = Session::new(Box::new(out.clone()), Box::new(err.clone()));
+        let input = "1 + 2\nvar x = 5;\nx * 2\n-\"a\"\n1 2\n@\nx = x + 1\n";
+        run(input.as_bytes(), &mut session).unwrap();
+
+        assert_eq!(out.contents(), "> 3\n> > 10\n> > > > 6\n> \n");
+        assert_eq!(
+            err.contents(),
+            "Operand must be a number.\n[line 1]\n\
+             [line 1] Error at '2': Expect operator between operands.\n\
+             [line 1, col 1] Error: Unexpected character: @\n"
+        );
+    }
+}
@@ -0,0 +1,103 @@
+#[cfg(test)]
+use std::cell::RefCell;
+use std::io::{self, BufWriter, Write};
+#[cfg(test)]
+use std::rc::Rc;
+
+use crate::errors::{AppError, AppResult};
+
+/// Destinations of all output produced by the lexer, parser and evaluator.
+///
+/// The command line uses stdout and stderr, embedders can redirect both into their own writers.
+pub struct Session {
+    /// Normal output, like tokens, syntax trees and values.
+    pub out: Box<dyn Write>,
+
+    /// Error messages.
+    pub err: Box<dyn Write>,
+
+    /// Prefix reported errors with their [`AppError::code`].
+    pub with_codes: bool,
+}
+
+impl Session {
+    pub fn new(out: Box<dyn Write>, err: Box<dyn Write>) -> Self {
+        Self {
+            out,
+            err,
+            with_codes: false,
+        }
+    }
+
+    /// Session writing to stdout and stderr.
+    ///
+    /// Stdout is wrapped in a [`BufWriter`] if `buffered` is true, remember to [`Session::flush`]
+    /// before exiting the process.
+    pub fn stdio(buffered: bool) -> Self {
+        let out: Box<dyn Write> = if buffered {
+            Box::new(BufWriter::new(io::stdout()))
+        } else {
+            Box::new(io::stdout())
+        };
+        Self::new(out, Box::new(io::stderr()))
+    }
+
+    /// Write `error` to `err`.
+    pub fn report(&mut self, error: &AppError) -> AppResult<()> {
+        if self.with_codes {
+            writeln!(self.err, "[{}] {}", error.code(), error)?;
+        } else {
+            writeln!(self.err, "{}", error)?;
+        }
+        Ok(())
+    }
+
+    /// Write `error` to `err`.
+    ///
+    /// An `error` caused by an [`AppError`] is reported as that [`AppError`] only, so that the
+    /// output keeps the lox error format, other errors are written with their context.
+    pub fn report_any(&mut self, error: &anyhow::Error) -> AppResult<()> {
+        match error.downcast_ref::<AppError>() {
+            Some(v) => self.report(v),
+            None => {
+                writeln!(self.err, "{:#}", error)?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Flush pending output.
+    ///
+    /// `out` is flushed first so that it does not show up after later error messages.
+    pub fn flush(&mut self) -> AppResult<()> {
+        self.out.flush()?;
+        self.err.flush()?;
+        Ok(())
+    }
+}
+
+/// Writer appending to a buffer shared with its clones, to check output written through a
+/// [`Session`] in tests.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub(crate) struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+#[cfg(test)]
+impl SharedBuffer {
+    /// Everything written so far.
+    pub(crate) fn contents(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
+
+#[cfg(test)]
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,115 @@
+//! Run the command line binary over source files and check its output and exit code.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Output of one run of the binary.
+struct Output {
+    stdout: String,
+    stderr: String,
+    code: i32,
+}
+
+/// Run the binary with `args`.
+fn run(args: &[&str]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_codecrafters-interpreter"))
+        .args(args)
+        .output()
+        .expect("failed to run the binary");
+    Output {
+        stdout: String::from_utf8(output.stdout).unwrap(),
+        stderr: String::from_utf8(output.stderr).unwrap(),
+        code: output.status.code().expect("killed by signal"),
+    }
+}
+
+/// Write `source` to a new file in the temporary directory.
+fn source_file(source: &str) -> PathBuf {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let path = std::env::temp_dir().join(format!(
+        "lox-cli-{}-{}.lox",
+        std::process::id(),
+        COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&path, source).unwrap();
+    path
+}
+
+/// Run `command` over a file containing `source`.
+fn run_source(command: &str, source: &str) -> Output {
+    let path = source_file(source);
+    let output = run(&[command, path.to_str().unwrap()]);
+    fs::remove_file(path).unwrap();
+    output
+}
+
+#[test]
+fn parse_bare_expression() {
+    let output = run_source("parse", "1 + 2 * 3");
+    assert_eq!(output.stdout, "(+ 1.0 (* 2.0 3.0))\n");
+    assert_eq!(output.code, 0);
+}
+
+#[test]
+fn evaluate_bare_expression() {
+    let output = run_source("evaluate", "1 + 2 * 3\n");
+    assert_eq!(output.stdout, "7\n");
+    assert_eq!(output.stderr, "");
+    assert_eq!(output.code, 0);
+}
+
+#[test]
+fn evaluate_prints_values_then_runtime_error() {
+    let output = run_source("evaluate", "\"a\" + \"b\";\n1 +\n  -\"x\";\n3;\n");
+    assert_eq!(output.stdout, "ab\n");
+    assert_eq!(output.stderr, "Operand must be a number.\n[line 3]\n");
+    assert_eq!(output.code, 70);
+}
+
+#[test]
+fn parse_error_keeps_lox_format() {
+    let output = run_source("parse", "1;\n(2;\n");
+    assert_eq!(output.stdout, "");
+    assert_eq!(
+        output.stderr,
+        "[line 2] Error at ';': Expect ')' after expression.\n"
+    );
+    assert_eq!(output.code, 65);
+}
+
+#[test]
+fn help_exits_zero() {
+    for flag in ["--help", "-h"] {
+        let output = run(&[flag]);
+        assert!(output.stdout.starts_with("Usage: "));
+        for command in ["tokenize", "parse", "evaluate", "repl"] {
+            assert!(output.stdout.contains(command));
+        }
+        assert_eq!(output.code, 0);
+    }
+}
+
+#[test]
+fn unknown_command_exits_64() {
+    let output = run(&["frobnicate", "file.lox"]);
+    assert_eq!(output.stdout, "");
+    assert!(output.stderr.starts_with("Unknown command: frobnicate\n"));
+    assert_eq!(output.code, 64);
+}
+
+#[test]
+fn lint_assignment_in_condition() {
+    let path = source_file("var x;\nif (x = 1) x;\n");
+    let output = run(&["evaluate", path.to_str().unwrap(), "--lint"]);
+    fs::remove_file(path).unwrap();
+    assert_eq!(
+        output.stderr,
+        "[line 2] Warning: Assignment in condition; did you mean '=='?\n"
+    );
+    assert_eq!(output.code, 0);
+
+    let output = run_source("evaluate", "var x;\nif (x = 1) x;\n");
+    assert_eq!(output.stderr, "");
+}